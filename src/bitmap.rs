@@ -0,0 +1,255 @@
+use bytes::{Bytes, BytesMut};
+
+/// The bit at `offset` of a string value.
+///
+/// Bits are numbered from the most significant bit of the first byte, as in
+/// Redis, so bit 0 of `"\x80"` is set. Reads past the end of a string see
+/// zeros, and writes past it grow the string with zero bytes.
+pub fn get_bit(bytes: &[u8], offset: usize) -> bool {
+    bytes
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets the bit at `offset`, returning its previous value.
+pub fn set_bit(bytes: &mut BytesMut, offset: usize, bit: bool) -> bool {
+    let index = offset / 8;
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let previous = bytes[index] & mask != 0;
+    if bit {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    previous
+}
+
+/// Resolves a `start end` range over `len` bytes or bits the way `BITCOUNT`
+/// and `BITPOS` do, or `None` if it selects nothing. Unlike `GETRANGE`, an
+/// end still negative after counting from the end clamps to 0.
+pub fn resolve_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return None;
+    }
+    let from_end = |index: i64| {
+        if index < 0 {
+            (len + index).max(0)
+        } else {
+            index
+        }
+    };
+    let (start, end) = (from_end(start), from_end(end).min(len - 1));
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+/// Number of set bits from bit `start` to bit `end`, inclusive, which must
+/// be within `bytes`.
+pub fn count(bytes: &[u8], start: usize, end: usize) -> usize {
+    let (first, last) = (start / 8, end / 8);
+    let total: usize = bytes[first..=last]
+        .iter()
+        .map(|byte| byte.count_ones() as usize)
+        .sum();
+    let before = bytes[first] & !(0xff >> (start % 8));
+    let after = bytes[last] & !(0xff << (7 - end % 8));
+    total - before.count_ones() as usize - after.count_ones() as usize
+}
+
+/// Position of the first bit equal to `bit` from bit `start` to bit `end`,
+/// inclusive, which must be within `bytes`.
+pub fn position(bytes: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    (start / 8..=end / 8).find_map(|index| {
+        // Look for a set bit, masking out those outside the range.
+        let mut byte = if bit { bytes[index] } else { !bytes[index] };
+        if index == start / 8 {
+            byte &= 0xff >> (start % 8);
+        }
+        if index == end / 8 {
+            byte &= 0xff << (7 - end % 8);
+        }
+        (byte != 0).then(|| index * 8 + byte.leading_zeros() as usize)
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    /// The bits of the first source set in none of the others.
+    Diff,
+}
+
+/// Combines `sources` byte by byte, the shorter ones padded with zeros.
+/// `Not` only looks at the first source.
+pub fn bitop(op: BitOp, sources: &[Bytes]) -> Vec<u8> {
+    let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+    let byte = |source: &Bytes, index: usize| source.get(index).copied().unwrap_or(0);
+    (0..len)
+        .map(|index| {
+            let mut bytes = sources.iter().map(|source| byte(source, index));
+            match op {
+                BitOp::And => bytes.fold(0xff, |acc, byte| acc & byte),
+                BitOp::Or => bytes.fold(0, |acc, byte| acc | byte),
+                BitOp::Xor => bytes.fold(0, |acc, byte| acc ^ byte),
+                BitOp::Not => !byte(&sources[0], index),
+                BitOp::Diff => {
+                    let first = bytes.next().unwrap_or(0);
+                    first & !bytes.fold(0, |acc, byte| acc | byte)
+                }
+            }
+        })
+        .collect()
+}
+
+/// A `BITFIELD` integer type such as `i8` or `u16`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl FieldType {
+    /// Parses `i1` to `i64` or `u1` to `u63`; unsigned values must fit in
+    /// the signed integers replies carry.
+    pub fn parse(arg: &[u8]) -> Option<Self> {
+        let (signed, bits) = match arg.split_first()? {
+            (b'i' | b'I', bits) => (true, bits),
+            (b'u' | b'U', bits) => (false, bits),
+            _ => return None,
+        };
+        let bits: u32 = std::str::from_utf8(bits).ok()?.parse().ok()?;
+        let max = if signed { 64 } else { 63 };
+        (1..=max).contains(&bits).then_some(Self { signed, bits })
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Brings `value` within range as `overflow` says, or `None` if it
+    /// says to fail.
+    pub fn fit(self, value: i128, overflow: Overflow) -> Option<i64> {
+        let (min, max) = (self.min(), self.max());
+        if (min..=max).contains(&value) {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat => Some(value.clamp(min, max) as i64),
+            Overflow::Wrap => {
+                let wrapped = value & ((1 << self.bits) - 1);
+                let wrapped = if wrapped > max {
+                    wrapped - (1 << self.bits)
+                } else {
+                    wrapped
+                };
+                Some(wrapped as i64)
+            }
+        }
+    }
+}
+
+/// What `BITFIELD` does with values that do not fit their type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// Reads the integer of type `ty` starting at bit `offset`.
+pub fn get_field(bytes: &[u8], offset: usize, ty: FieldType) -> i64 {
+    let mut value: u64 = 0;
+    for i in 0..ty.bits as usize {
+        value = value << 1 | get_bit(bytes, offset + i) as u64;
+    }
+    if ty.signed && ty.bits < 64 && value & (1 << (ty.bits - 1)) != 0 {
+        // Sign-extend.
+        value |= u64::MAX << ty.bits;
+    }
+    value as i64
+}
+
+/// Writes the low `ty.bits` bits of `value` starting at bit `offset`.
+pub fn set_field(bytes: &mut BytesMut, offset: usize, ty: FieldType, value: i64) {
+    let last = (offset + ty.bits as usize - 1) / 8;
+    if bytes.len() <= last {
+        bytes.resize(last + 1, 0);
+    }
+    for i in 0..ty.bits as usize {
+        let bit = (value as u64 >> (ty.bits as usize - 1 - i)) & 1 == 1;
+        set_bit(bytes, offset + i, bit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use super::{
+        bitop, count, get_field, position, resolve_range, set_field, BitOp, FieldType, Overflow,
+    };
+
+    #[test]
+    fn counts_and_finds_bits_within_ranges() {
+        let bytes = b"\xff\xf0\x00";
+        assert_eq!(count(bytes, 0, 23), 12);
+        assert_eq!(count(bytes, 4, 11), 8);
+        assert_eq!(count(bytes, 9, 9), 1);
+        assert_eq!(position(bytes, false, 0, 23), Some(12));
+        assert_eq!(position(bytes, true, 3, 23), Some(3));
+        assert_eq!(position(bytes, true, 12, 23), None);
+        assert_eq!(resolve_range(-100, -100, 3), Some((0, 0)));
+        assert_eq!(resolve_range(-1, -2, 3), None);
+        assert_eq!(resolve_range(1, 100, 3), Some((1, 2)));
+    }
+
+    #[test]
+    fn combines_sources_of_different_lengths() {
+        let sources = [Bytes::from_static(b"\xf0\x0f"), Bytes::from_static(b"\x3c")];
+        assert_eq!(bitop(BitOp::And, &sources), b"\x30\x00");
+        assert_eq!(bitop(BitOp::Or, &sources), b"\xfc\x0f");
+        assert_eq!(bitop(BitOp::Xor, &sources), b"\xcc\x0f");
+        assert_eq!(bitop(BitOp::Diff, &sources), b"\xc0\x0f");
+        assert_eq!(bitop(BitOp::Not, &sources[1..]), b"\xc3");
+    }
+
+    #[test]
+    fn fields_wrap_saturate_or_fail() {
+        let i8 = FieldType::parse(b"i8").unwrap();
+        let u4 = FieldType::parse(b"u4").unwrap();
+        assert_eq!(FieldType::parse(b"u64"), None);
+        assert_eq!(FieldType::parse(b"i0"), None);
+
+        assert_eq!(i8.fit(128, Overflow::Wrap), Some(-128));
+        assert_eq!(i8.fit(-129, Overflow::Sat), Some(-128));
+        assert_eq!(i8.fit(200, Overflow::Fail), None);
+        assert_eq!(u4.fit(17, Overflow::Wrap), Some(1));
+        assert_eq!(u4.fit(-1, Overflow::Sat), Some(0));
+
+        let mut bytes = BytesMut::new();
+        set_field(&mut bytes, 5, i8, -2);
+        assert_eq!(&bytes[..], b"\x07\xf0");
+        assert_eq!(get_field(&bytes, 5, i8), -2);
+        assert_eq!(get_field(&bytes, 5, u4), 15);
+        assert_eq!(get_field(&bytes, 100, i8), 0);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use crate::{
+    commands::string::parse_f64,
+    resp::{resp::Value, RespError},
+    server::Client,
+};
+
+/// What a blocking command asks for when it cannot be served right away:
+/// the keys to wait on, how long to wait and what to reply if nothing
+/// arrives in time.
+#[derive(Clone, Debug)]
+pub struct BlockRequest {
+    pub keys: Vec<Bytes>,
+    /// `None` waits forever.
+    pub timeout: Option<Duration>,
+    pub timeout_reply: Value,
+    /// Arguments to run the command again with instead of its own.
+    pub retry_args: Option<Vec<Bytes>>,
+}
+
+/// A parked client: a snapshot of its connection state, the command to run
+/// again once one of its keys is ready, and where to deliver the reply.
+pub struct BlockedClient {
+    pub client: Client,
+    pub argv: Vec<Bytes>,
+    keys: Vec<Bytes>,
+    reply: oneshot::Sender<Value>,
+}
+
+/// Clients blocked on keys, like Redis' `db->blocking_keys` and
+/// `server.ready_keys`.
+///
+/// Waiters on each key are kept in the order they blocked, so the client
+/// that has waited longest is served first.
+#[derive(Default)]
+pub struct Blocking {
+    waiting: HashMap<Bytes, VecDeque<u64>>,
+    clients: HashMap<u64, BlockedClient>,
+    /// Keys written since the last time blocked clients were served, in the
+    /// order they were signalled.
+    ready: Vec<Bytes>,
+}
+
+impl Blocking {
+    /// Parks `client` until one of `keys` is signalled, to then run `argv`
+    /// again on its behalf.
+    pub fn block(
+        &mut self,
+        client: Client,
+        argv: Vec<Bytes>,
+        keys: Vec<Bytes>,
+        reply: oneshot::Sender<Value>,
+    ) {
+        for key in &keys {
+            let queue = self.waiting.entry(key.clone()).or_default();
+            if !queue.contains(&client.id) {
+                queue.push_back(client.id);
+            }
+        }
+        let id = client.id;
+        self.clients.insert(
+            id,
+            BlockedClient {
+                client,
+                argv,
+                keys,
+                reply,
+            },
+        );
+    }
+
+    /// Removes the client from every queue it waits in, returning whether it
+    /// was still blocked (it may have been served in the meantime).
+    pub fn unblock(&mut self, id: u64) -> bool {
+        let Some(blocked) = self.clients.remove(&id) else {
+            return false;
+        };
+        self.forget(id, &blocked.keys);
+        true
+    }
+
+    fn forget(&mut self, id: u64, keys: &[Bytes]) {
+        for key in keys {
+            if let Some(queue) = self.waiting.get_mut(key) {
+                queue.retain(|&waiter| waiter != id);
+                if queue.is_empty() {
+                    self.waiting.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Notes that `key` was written, if anybody is waiting for it.
+    pub fn signal(&mut self, key: &[u8]) {
+        if self.waiting.contains_key(key) && !self.ready.iter().any(|ready| ready == key) {
+            self.ready.push(Bytes::copy_from_slice(key));
+        }
+    }
+
+    pub fn take_ready(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.ready)
+    }
+
+    /// Ids of the clients waiting on `key`, longest waiting first.
+    pub fn waiters(&self, key: &[u8]) -> Vec<u64> {
+        self.waiting
+            .get(key)
+            .map_or_else(Vec::new, |queue| queue.iter().copied().collect())
+    }
+
+    /// Takes a blocked client out to retry its command; it must be handed
+    /// back with [`Blocking::park_again`] or [`Blocking::finish`].
+    pub fn take(&mut self, id: u64) -> Option<BlockedClient> {
+        self.clients.remove(&id)
+    }
+
+    /// Puts back a client whose retried command had to block again.
+    pub fn park_again(&mut self, blocked: BlockedClient) {
+        self.clients.insert(blocked.client.id, blocked);
+    }
+
+    /// Unblocks a client whose retried command completed, delivering `reply`.
+    pub fn finish(&mut self, blocked: BlockedClient, reply: Value) {
+        self.forget(blocked.client.id, &blocked.keys);
+        // The connection may have gone away since; nobody is left to tell.
+        let _ = blocked.reply.send(reply);
+    }
+
+    /// Number of clients currently blocked, for `INFO clients`.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+/// Parses the timeout of the blocking commands: seconds, possibly
+/// fractional, where 0 means forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>, RespError> {
+    let seconds = parse_f64(arg)
+        .map_err(|_| RespError::Other("timeout is not a float or out of range".to_owned()))?;
+    if seconds < 0.0 {
+        return Err(RespError::Other("timeout is negative".to_owned()));
+    }
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .map(Some)
+        .map_err(|_| RespError::Other("timeout is out of range".to_owned()))
+}
@@ -0,0 +1,386 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{keyword, string::parse_i64, CommandSpec, Context};
+use crate::{
+    bitmap::{self, BitOp, FieldType, Overflow},
+    resp::{
+        resp::{Value, MAX_BULK_LEN},
+        RespError,
+    },
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "setbit",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.",
+        since: "2.2.0",
+        handler: setbit,
+    },
+    CommandSpec {
+        name: "getbit",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Returns a bit value by offset.",
+        since: "2.2.0",
+        handler: getbit,
+    },
+    CommandSpec {
+        name: "bitcount",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Counts the number of set bits (population counting) in a string.",
+        since: "2.6.0",
+        handler: bitcount,
+    },
+    CommandSpec {
+        name: "bitpos",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Finds the first set (1) or clear (0) bit in a string.",
+        since: "2.8.7",
+        handler: bitpos,
+    },
+    CommandSpec {
+        name: "bitop",
+        arity: -4,
+        flags: &["write", "denyoom"],
+        first_key: 2,
+        last_key: -1,
+        step: 1,
+        group: "bitmap",
+        summary: "Performs bitwise operations on multiple strings, and stores the result.",
+        since: "2.6.0",
+        handler: bitop,
+    },
+    CommandSpec {
+        name: "bitfield",
+        arity: -2,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Performs arbitrary bitfield integer operations on strings.",
+        since: "3.2.0",
+        handler: bitfield,
+    },
+    CommandSpec {
+        name: "bitfield_ro",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "bitmap",
+        summary: "Performs arbitrary read-only bitfield integer operations on strings.",
+        since: "6.0.0",
+        handler: bitfield_ro,
+    },
+];
+
+/// Parses a bit offset, which must address a bit of a string no longer
+/// than `proto-max-bulk-len`.
+fn parse_bit_offset(arg: &[u8]) -> Result<usize, RespError> {
+    let offset = parse_i64(arg)
+        .ok()
+        .filter(|offset| (0..MAX_BULK_LEN * 8).contains(offset));
+    offset
+        .map(|offset| offset as usize)
+        .ok_or_else(|| RespError::Other("bit offset is not an integer or out of range".to_owned()))
+}
+
+/// `SETBIT key offset value`
+fn setbit(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let offset = parse_bit_offset(&args[1])?;
+    let bit = match args[2].as_ref() {
+        b"0" => false,
+        b"1" => true,
+        _ => {
+            return Err(RespError::Other(
+                "bit is not an integer or out of range".to_owned(),
+            ))
+        }
+    };
+    let previous = ctx
+        .db
+        .with_string_mut(&args[0], |bytes| bitmap::set_bit(bytes, offset, bit))?;
+    Ok(Value::Integer(previous as i64))
+}
+
+/// `GETBIT key offset`
+fn getbit(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let offset = parse_bit_offset(&args[1])?;
+    let bytes = ctx.db.get_string(&args[0])?.unwrap_or_default();
+    Ok(Value::Integer(bitmap::get_bit(&bytes, offset) as i64))
+}
+
+/// Parses the `BYTE | BIT` unit of a range, returning whether it counts
+/// bits.
+fn parse_unit(arg: &[u8]) -> Result<bool, RespError> {
+    match keyword(arg).as_str() {
+        "byte" => Ok(false),
+        "bit" => Ok(true),
+        _ => Err(RespError::Syntax),
+    }
+}
+
+/// The first and last bits of `bytes` a `start end [BYTE | BIT]` range
+/// selects, if any.
+fn bit_range(bytes: &[u8], start: i64, end: i64, in_bits: bool) -> Option<(usize, usize)> {
+    if in_bits {
+        bitmap::resolve_range(start, end, bytes.len() * 8)
+    } else {
+        bitmap::resolve_range(start, end, bytes.len()).map(|(start, end)| (start * 8, end * 8 + 7))
+    }
+}
+
+/// `BITCOUNT key [start end [BYTE | BIT]]`
+fn bitcount(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (start, end, in_bits) = match &args[1..] {
+        [] => (0, -1, false),
+        [start, end] => (parse_i64(start)?, parse_i64(end)?, false),
+        [start, end, unit] => (parse_i64(start)?, parse_i64(end)?, parse_unit(unit)?),
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(bytes) = ctx.db.get_string(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let count = bit_range(&bytes, start, end, in_bits)
+        .map_or(0, |(first, last)| bitmap::count(&bytes, first, last));
+    Ok(Value::Integer(count as i64))
+}
+
+/// `BITPOS key bit [start [end [BYTE | BIT]]]`
+fn bitpos(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let bit = match parse_i64(&args[1])? {
+        0 => false,
+        1 => true,
+        _ => {
+            return Err(RespError::Other(
+                "The bit argument must be 1 or 0.".to_owned(),
+            ))
+        }
+    };
+    let (start, end, in_bits) = match &args[2..] {
+        [] => (0, None, false),
+        [start] => (parse_i64(start)?, None, false),
+        [start, end] => (parse_i64(start)?, Some(parse_i64(end)?), false),
+        [start, end, unit] => (parse_i64(start)?, Some(parse_i64(end)?), parse_unit(unit)?),
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(bytes) = ctx.db.get_string(&args[0])? else {
+        return Ok(Value::Integer(if bit { -1 } else { 0 }));
+    };
+    let Some((first, last)) = bit_range(&bytes, start, end.unwrap_or(-1), in_bits) else {
+        return Ok(Value::Integer(-1));
+    };
+    let position = match bitmap::position(&bytes, bit, first, last) {
+        Some(position) => position as i64,
+        // Without an explicit end, the string counts as followed by zeros.
+        None if !bit && end.is_none() => (last + 1) as i64,
+        None => -1,
+    };
+    Ok(Value::Integer(position))
+}
+
+/// `BITOP AND | OR | XOR | NOT | DIFF destkey key [key ...]`
+fn bitop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let op = match keyword(&args[0]).as_str() {
+        "and" => BitOp::And,
+        "or" => BitOp::Or,
+        "xor" => BitOp::Xor,
+        "not" => BitOp::Not,
+        "diff" => BitOp::Diff,
+        _ => return Err(RespError::Syntax),
+    };
+    let (destination, keys) = (&args[1], &args[2..]);
+    if op == BitOp::Not && keys.len() != 1 {
+        return Err(RespError::Other(
+            "BITOP NOT must be called with a single source key.".to_owned(),
+        ));
+    }
+    if op == BitOp::Diff && keys.len() < 2 {
+        return Err(RespError::Other(
+            "BITOP DIFF must be called with at least two source keys.".to_owned(),
+        ));
+    }
+    let mut sources = Vec::with_capacity(keys.len());
+    for key in keys {
+        sources.push(ctx.db.get_string(key)?.unwrap_or_default());
+    }
+    let result = bitmap::bitop(op, &sources);
+    let len = result.len();
+    ctx.db.remove(destination);
+    if len > 0 {
+        ctx.db.set(destination.clone(), result.into(), None);
+    }
+    Ok(Value::Integer(len as i64))
+}
+
+enum FieldOp {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// One `GET`, `SET` or `INCRBY` of a `BITFIELD` call, with the `OVERFLOW`
+/// behavior in effect for it.
+struct FieldCommand {
+    op: FieldOp,
+    ty: FieldType,
+    offset: usize,
+    overflow: Overflow,
+}
+
+/// Parses the subcommands of `BITFIELD`, or of `BITFIELD_RO` if
+/// `read_only`.
+fn parse_field_commands(args: &[Bytes], read_only: bool) -> Result<Vec<FieldCommand>, RespError> {
+    let mut commands = vec![];
+    let mut overflow = Overflow::Wrap;
+    let mut i = 0;
+    while i < args.len() {
+        let subcommand = keyword(&args[i]);
+        let operands = match subcommand.as_str() {
+            "get" => 2,
+            "set" | "incrby" => 3,
+            "overflow" => 1,
+            _ => return Err(RespError::Syntax),
+        };
+        let Some(operands) = args.get(i + 1..i + 1 + operands) else {
+            return Err(RespError::Syntax);
+        };
+        i += 1 + operands.len();
+        if read_only && subcommand != "get" {
+            return Err(RespError::Other(
+                "BITFIELD_RO only supports the GET subcommand".to_owned(),
+            ));
+        }
+        if subcommand == "overflow" {
+            overflow = match keyword(&operands[0]).as_str() {
+                "wrap" => Overflow::Wrap,
+                "sat" => Overflow::Sat,
+                "fail" => Overflow::Fail,
+                _ => {
+                    return Err(RespError::Other(
+                        "Invalid OVERFLOW type specified".to_owned(),
+                    ))
+                }
+            };
+            continue;
+        }
+
+        let ty = FieldType::parse(&operands[0]).ok_or_else(|| {
+            RespError::Other(
+                "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+                    .to_owned(),
+            )
+        })?;
+        // `#n` addresses the n-th field of this type.
+        let offset = match operands[1].strip_prefix(b"#") {
+            Some(index) => parse_bit_offset(index)?
+                .checked_mul(ty.bits as usize)
+                .filter(|&offset| (offset as i64) < MAX_BULK_LEN * 8)
+                .ok_or_else(|| {
+                    RespError::Other("bit offset is not an integer or out of range".to_owned())
+                })?,
+            None => parse_bit_offset(&operands[1])?,
+        };
+        let op = match subcommand.as_str() {
+            "get" => FieldOp::Get,
+            "set" => FieldOp::Set(parse_i64(&operands[2])?),
+            _ => FieldOp::IncrBy(parse_i64(&operands[2])?),
+        };
+        commands.push(FieldCommand {
+            op,
+            ty,
+            offset,
+            overflow,
+        });
+    }
+    Ok(commands)
+}
+
+/// `BITFIELD key [GET type offset | [OVERFLOW WRAP | SAT | FAIL]
+/// SET type offset value | INCRBY type offset increment] ...`, or
+/// `BITFIELD_RO key [GET type offset ...]` if `read_only`.
+fn run_bitfield(ctx: &mut Context, args: &[Bytes], read_only: bool) -> Result<Value, RespError> {
+    let commands = parse_field_commands(&args[1..], read_only)?;
+    // Like Redis, any write makes the string long enough for every field
+    // written, even those an overflow ends up skipping.
+    let written = commands
+        .iter()
+        .filter(|command| !matches!(command.op, FieldOp::Get))
+        .map(|command| command.offset + command.ty.bits as usize)
+        .max();
+    let Some(end) = written else {
+        let bytes = ctx.db.get_string(&args[0])?.unwrap_or_default();
+        let replies = commands
+            .iter()
+            .map(|command| Value::Integer(bitmap::get_field(&bytes, command.offset, command.ty)))
+            .collect();
+        return Ok(Value::Array(replies));
+    };
+    let replies = ctx.db.with_string_mut(&args[0], |bytes| {
+        let len = end.div_ceil(8);
+        if bytes.len() < len {
+            bytes.resize(len, 0);
+        }
+        run_field_commands(bytes, &commands)
+    })?;
+    Ok(Value::Array(replies))
+}
+
+/// Runs the subcommands of a `BITFIELD` that writes, returning their
+/// replies.
+fn run_field_commands(bytes: &mut BytesMut, commands: &[FieldCommand]) -> Vec<Value> {
+    let mut replies = Vec::with_capacity(commands.len());
+    for command in commands {
+        let (ty, offset) = (command.ty, command.offset);
+        let current = bitmap::get_field(bytes, offset, ty);
+        let (value, reply_with_old) = match command.op {
+            FieldOp::Get => {
+                replies.push(Value::Integer(current));
+                continue;
+            }
+            // Unsigned fields take the value's two's complement bits, as in
+            // Redis.
+            FieldOp::Set(value) if ty.signed => (value as i128, true),
+            FieldOp::Set(value) => (value as u64 as i128, true),
+            FieldOp::IncrBy(increment) => (current as i128 + increment as i128, false),
+        };
+        match ty.fit(value, command.overflow) {
+            Some(value) => {
+                bitmap::set_field(bytes, offset, ty, value);
+                replies.push(Value::Integer(if reply_with_old { current } else { value }));
+            }
+            None => replies.push(Value::Null),
+        }
+    }
+    replies
+}
+
+fn bitfield(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    run_bitfield(ctx, args, false)
+}
+
+fn bitfield_ro(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    run_bitfield(ctx, args, true)
+}
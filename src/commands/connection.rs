@@ -0,0 +1,154 @@
+use bytes::Bytes;
+
+use super::{bulk, keyword, ok, CommandSpec, Context};
+use crate::resp::{
+    resp::{Protocol, Value},
+    RespError,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Returns the server's liveliness response.",
+        since: "1.0.0",
+        handler: ping,
+    },
+    CommandSpec {
+        name: "echo",
+        arity: 2,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Returns the given string.",
+        since: "1.0.0",
+        handler: echo,
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Handshakes with the Redis server.",
+        since: "6.0.0",
+        handler: hello,
+    },
+    CommandSpec {
+        name: "client",
+        arity: -2,
+        flags: &["noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "A container for client connection commands.",
+        since: "2.4.0",
+        handler: client,
+    },
+];
+
+fn ping(_ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(match args {
+        [] => Value::SimpleString("PONG".to_owned()),
+        [message] => bulk(message.clone()),
+        _ => return Err(RespError::WrongArity("ping".to_owned())),
+    })
+}
+
+fn echo(_ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(bulk(args[0].clone()))
+}
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+fn hello(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let mut protocol = ctx.client.protocol;
+    let mut name = ctx.client.name.clone();
+    let mut args = args.iter();
+    if let Some(version) = args.next() {
+        protocol = match version.as_ref() {
+            b"2" => Protocol::Resp2,
+            b"3" => Protocol::Resp3,
+            _ => {
+                return Err(RespError::Prefixed(
+                    "NOPROTO unsupported protocol version".to_owned(),
+                ))
+            }
+        };
+    }
+    while let Some(option) = args.next() {
+        match keyword(option).as_str() {
+            "auth" => {
+                let (Some(user), Some(_password)) = (args.next(), args.next()) else {
+                    return Err(RespError::Other(
+                        "Syntax error in HELLO option 'auth'".to_owned(),
+                    ));
+                };
+                // Only the passwordless default user exists.
+                if user.as_ref() != b"default" {
+                    return Err(RespError::Prefixed(
+                        "WRONGPASS invalid username-password pair or user is disabled.".to_owned(),
+                    ));
+                }
+            }
+            "setname" => {
+                let Some(new_name) = args.next() else {
+                    return Err(RespError::Other(
+                        "Syntax error in HELLO option 'setname'".to_owned(),
+                    ));
+                };
+                name = Some(new_name.clone());
+            }
+            other => {
+                return Err(RespError::Other(format!(
+                    "Syntax error in HELLO option '{}'",
+                    other
+                )))
+            }
+        }
+    }
+
+    ctx.client.protocol = protocol;
+    ctx.client.name = name;
+    let field = |name: &'static str, value: Value| (bulk(name), value);
+    Ok(Value::Map(vec![
+        field("server", bulk("redis")),
+        field("version", bulk("7.4.0")),
+        field("proto", Value::Integer(protocol.version())),
+        field("id", Value::Integer(ctx.client.id as i64)),
+        field("mode", bulk("standalone")),
+        field("role", bulk("master")),
+        field("modules", Value::Array(vec![])),
+    ]))
+}
+
+/// `CLIENT ID|GETNAME|SETNAME`
+fn client(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(match (keyword(&args[0]).as_str(), args.len()) {
+        ("id", 1) => Value::Integer(ctx.client.id as i64),
+        ("getname", 1) => ctx
+            .client
+            .name
+            .clone()
+            .map_or(Value::Null, Value::BulkString),
+        ("setname", 2) => {
+            ctx.client.name = Some(args[1].clone());
+            ok()
+        }
+        (other, _) => {
+            return Err(RespError::Other(format!(
+                "unknown subcommand or wrong number of arguments for '{}'. Try CLIENT HELP.",
+                other
+            )))
+        }
+    })
+}
@@ -0,0 +1,612 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{bulk, keyword, ok, string::parse_i64, CommandSpec, Context};
+use crate::{
+    glob::glob_match,
+    resp::{resp::Value, RespError},
+    storage::Item,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "keys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Returns all key names that match a pattern.",
+        since: "1.0.0",
+        handler: keys,
+    },
+    CommandSpec {
+        name: "del",
+        arity: -2,
+        flags: &["write"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Deletes one or more keys.",
+        since: "1.0.0",
+        handler: del,
+    },
+    CommandSpec {
+        name: "unlink",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Asynchronously deletes one or more keys.",
+        since: "4.0.0",
+        handler: unlink,
+    },
+    CommandSpec {
+        name: "exists",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "generic",
+        summary: "Determines whether one or more keys exist.",
+        since: "1.0.0",
+        handler: exists,
+    },
+    CommandSpec {
+        name: "type",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Determines the type of value stored at a key.",
+        since: "1.0.0",
+        handler: type_,
+    },
+    CommandSpec {
+        name: "rename",
+        arity: 3,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Renames a key and overwrites the destination.",
+        since: "1.0.0",
+        handler: rename,
+    },
+    CommandSpec {
+        name: "renamenx",
+        arity: 3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Renames a key only when the target key name doesn't exist.",
+        since: "1.0.0",
+        handler: renamenx,
+    },
+    CommandSpec {
+        name: "copy",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Copies the value of a key to a new key.",
+        since: "6.2.0",
+        handler: copy,
+    },
+    CommandSpec {
+        name: "object",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "A container for object introspection commands.",
+        since: "2.2.3",
+        handler: object,
+    },
+    CommandSpec {
+        name: "randomkey",
+        arity: 1,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Returns a random key name from the database.",
+        since: "1.0.0",
+        handler: randomkey,
+    },
+    CommandSpec {
+        name: "dbsize",
+        arity: 1,
+        flags: &["readonly", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns the number of keys in the database.",
+        since: "1.0.0",
+        handler: dbsize,
+    },
+    CommandSpec {
+        name: "expire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key in seconds.",
+        since: "1.0.0",
+        handler: expire,
+    },
+    CommandSpec {
+        name: "pexpire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key in milliseconds.",
+        since: "2.6.0",
+        handler: pexpire,
+    },
+    CommandSpec {
+        name: "expireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix timestamp.",
+        since: "1.2.0",
+        handler: expireat,
+    },
+    CommandSpec {
+        name: "pexpireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        since: "2.6.0",
+        handler: pexpireat,
+    },
+    CommandSpec {
+        name: "ttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time in seconds of a key.",
+        since: "1.0.0",
+        handler: ttl,
+    },
+    CommandSpec {
+        name: "pttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time in milliseconds of a key.",
+        since: "2.6.0",
+        handler: pttl,
+    },
+    CommandSpec {
+        name: "expiretime",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix timestamp.",
+        since: "7.0.0",
+        handler: expiretime,
+    },
+    CommandSpec {
+        name: "pexpiretime",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+        since: "7.0.0",
+        handler: pexpiretime,
+    },
+    CommandSpec {
+        name: "persist",
+        arity: 2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Removes the expiration time of a key.",
+        since: "2.2.0",
+        handler: persist,
+    },
+    CommandSpec {
+        name: "scan",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Iterates over the key names in the database.",
+        since: "2.8.0",
+        handler: scan,
+    },
+];
+
+fn keys(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ctx.db.keys(&args[0]))
+}
+
+/// Items costing more than this many allocations to free are dropped on a
+/// blocking thread by `UNLINK`, like Redis' `LAZYFREE_THRESHOLD`.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+fn del(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let deleted = args
+        .iter()
+        .filter(|key| ctx.db.remove(key).is_some())
+        .count();
+    Ok(Value::Integer(deleted as i64))
+}
+
+fn unlink(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let removed: Vec<Item> = args.iter().filter_map(|key| ctx.db.remove(key)).collect();
+    let deleted = removed.len();
+    let effort: usize = removed.iter().map(Item::free_effort).sum();
+    if effort > LAZYFREE_THRESHOLD {
+        tokio::task::spawn_blocking(move || drop(removed));
+    }
+    Ok(Value::Integer(deleted as i64))
+}
+
+fn exists(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let found = args
+        .iter()
+        .filter(|key| ctx.db.lookup(key).is_some())
+        .count();
+    Ok(Value::Integer(found as i64))
+}
+
+fn type_(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let name = ctx.db.lookup(&args[0]).map_or("none", Item::type_name);
+    Ok(Value::SimpleString(name.to_owned()))
+}
+
+/// Moves the item at `source` to `destination`, TTL included.
+fn rename_key(
+    ctx: &mut Context,
+    source: &Bytes,
+    destination: &Bytes,
+    nx: bool,
+) -> Result<bool, RespError> {
+    if ctx.db.lookup(source).is_none() {
+        return Err(RespError::Other("no such key".to_owned()));
+    }
+    if source == destination {
+        return Ok(!nx);
+    }
+    if nx && ctx.db.lookup(destination).is_some() {
+        return Ok(false);
+    }
+    let item = ctx
+        .db
+        .remove(source)
+        .expect("source key was just looked up");
+    ctx.db.insert(destination.clone(), item);
+    Ok(true)
+}
+
+fn rename(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    rename_key(ctx, &args[0], &args[1], false)?;
+    Ok(ok())
+}
+
+fn renamenx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let renamed = rename_key(ctx, &args[0], &args[1], true)?;
+    Ok(Value::Integer(renamed as i64))
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+fn copy(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (source, destination) = (&args[0], &args[1]);
+    let mut replace = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match keyword(option).as_str() {
+            "replace" => replace = true,
+            // Only database 0 exists.
+            "db" => match options.next().map(|db| db.as_ref()) {
+                Some(b"0") => {}
+                Some(_) => return Err(RespError::Other("DB index is out of range".to_owned())),
+                None => return Err(RespError::Syntax),
+            },
+            _ => return Err(RespError::Syntax),
+        }
+    }
+
+    if source == destination {
+        return Err(RespError::Other(
+            "source and destination objects are the same".to_owned(),
+        ));
+    }
+    let Some(item) = ctx.db.lookup(source).cloned() else {
+        return Ok(Value::Integer(0));
+    };
+    if !replace && ctx.db.lookup(destination).is_some() {
+        return Ok(Value::Integer(0));
+    }
+    ctx.db.insert(destination.clone(), item);
+    Ok(Value::Integer(1))
+}
+
+/// `OBJECT ENCODING key`
+fn object(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    match (keyword(&args[0]).as_str(), &args[1..]) {
+        ("encoding", [key]) => Ok(ctx
+            .db
+            .lookup(key)
+            .map_or(Value::Null, |item| bulk(item.encoding()))),
+        (other, _) => Err(RespError::Other(format!(
+            "unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+            other
+        ))),
+    }
+}
+
+fn randomkey(ctx: &mut Context, _args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ctx.db.random_key().map_or(Value::Null, bulk))
+}
+
+fn dbsize(ctx: &mut Context, _args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(Value::Integer(ctx.db.len() as i64))
+}
+
+/// Unit and origin of the time argument of the `EXPIRE` family.
+#[derive(Clone, Copy)]
+pub(crate) enum ExpireBase {
+    Seconds,
+    Milliseconds,
+    UnixSeconds,
+    UnixMilliseconds,
+}
+
+pub(crate) fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as i64
+}
+
+/// Shared implementation of `EXPIRE`, `PEXPIRE`, `EXPIREAT` and `PEXPIREAT`
+/// with their `NX | XX | GT | LT` conditions.
+fn expire_generic(
+    ctx: &mut Context,
+    args: &[Bytes],
+    base: ExpireBase,
+    command: &str,
+) -> Result<Value, RespError> {
+    let amount = parse_i64(&args[1])?;
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for option in &args[2..] {
+        match keyword(option).as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "gt" => gt = true,
+            "lt" => lt = true,
+            other => return Err(RespError::Other(format!("Unsupported option {}", other))),
+        }
+    }
+    if nx && (xx || gt || lt) {
+        return Err(RespError::Other(
+            "NX and XX, GT or LT options at the same time are not compatible".to_owned(),
+        ));
+    }
+    if gt && lt {
+        return Err(RespError::Other(
+            "GT and LT options at the same time are not compatible".to_owned(),
+        ));
+    }
+
+    let invalid = || RespError::Other(format!("invalid expire time in '{}' command", command));
+    let now = unix_millis(SystemTime::now());
+    let deadline = match base {
+        ExpireBase::Seconds => amount.checked_mul(1000).and_then(|ms| ms.checked_add(now)),
+        ExpireBase::Milliseconds => amount.checked_add(now),
+        ExpireBase::UnixSeconds => amount.checked_mul(1000),
+        ExpireBase::UnixMilliseconds => Some(amount),
+    }
+    .ok_or_else(invalid)?;
+
+    let Some(item) = ctx.db.lookup_mut(&args[0]) else {
+        return Ok(Value::Integer(0));
+    };
+    let current = item.ttl.map(unix_millis);
+    let allowed = match current {
+        // A key without a TTL counts as expiring infinitely far away.
+        None => !xx && !gt,
+        Some(current) => !nx && (!gt || deadline > current) && (!lt || deadline < current),
+    };
+    if !allowed {
+        return Ok(Value::Integer(0));
+    }
+
+    if deadline <= now {
+        ctx.db.remove(&args[0]);
+    } else {
+        item.ttl = Some(UNIX_EPOCH + Duration::from_millis(deadline as u64));
+    }
+    Ok(Value::Integer(1))
+}
+
+fn expire(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    expire_generic(ctx, args, ExpireBase::Seconds, "expire")
+}
+
+fn pexpire(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    expire_generic(ctx, args, ExpireBase::Milliseconds, "pexpire")
+}
+
+fn expireat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    expire_generic(ctx, args, ExpireBase::UnixSeconds, "expireat")
+}
+
+fn pexpireat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    expire_generic(ctx, args, ExpireBase::UnixMilliseconds, "pexpireat")
+}
+
+/// Returns -2 for a missing key, -1 for a key without TTL, and otherwise
+/// the key's TTL converted by `convert` from (deadline, now) in milliseconds.
+fn ttl_generic(ctx: &mut Context, key: &[u8], convert: fn(i64, i64) -> i64) -> Value {
+    match ctx.db.lookup(key) {
+        None => Value::Integer(-2),
+        Some(item) => match item.ttl {
+            None => Value::Integer(-1),
+            Some(ttl) => Value::Integer(convert(unix_millis(ttl), unix_millis(SystemTime::now()))),
+        },
+    }
+}
+
+fn ttl(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ttl_generic(ctx, &args[0], |deadline, now| {
+        ((deadline - now).max(0) + 500) / 1000
+    }))
+}
+
+fn pttl(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ttl_generic(ctx, &args[0], |deadline, now| {
+        (deadline - now).max(0)
+    }))
+}
+
+fn expiretime(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ttl_generic(ctx, &args[0], |deadline, _| deadline / 1000))
+}
+
+fn pexpiretime(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(ttl_generic(ctx, &args[0], |deadline, _| deadline))
+}
+
+fn persist(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let persisted = ctx
+        .db
+        .lookup_mut(&args[0])
+        .and_then(|item| item.ttl.take())
+        .is_some();
+    Ok(Value::Integer(persisted as i64))
+}
+
+/// Options shared by `SCAN` and the per-collection `*SCAN` commands.
+pub(crate) struct ScanOptions {
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    pub type_name: Option<String>,
+    pub novalues: bool,
+}
+
+/// What a `*SCAN` command walks, which decides the options it takes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScanTarget {
+    /// `SCAN`, which also takes `TYPE`.
+    Keyspace,
+    /// `HSCAN`, which also takes `NOVALUES`.
+    Hash,
+    /// `SSCAN` and `ZSCAN`.
+    Members,
+}
+
+impl ScanOptions {
+    pub fn matches(&self, name: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, name, false))
+    }
+}
+
+pub(crate) fn parse_cursor(arg: &[u8]) -> Result<u64, RespError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or_else(|| RespError::Other("invalid cursor".to_owned()))
+}
+
+/// Parses `[MATCH pattern] [COUNT count]`, plus `[TYPE type]` for the
+/// keyspace and `[NOVALUES]` for hashes.
+pub(crate) fn parse_scan_options(
+    args: &[Bytes],
+    target: ScanTarget,
+) -> Result<ScanOptions, RespError> {
+    let mut options = ScanOptions {
+        pattern: None,
+        count: 10,
+        type_name: None,
+        novalues: false,
+    };
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        if target == ScanTarget::Hash && keyword(option) == "novalues" {
+            options.novalues = true;
+            continue;
+        }
+        let value = args.next().ok_or(RespError::Syntax)?;
+        match keyword(option).as_str() {
+            "match" => options.pattern = Some(value.clone()),
+            "count" => {
+                let count = parse_i64(value)?;
+                if count < 1 {
+                    return Err(RespError::Syntax);
+                }
+                options.count = count as usize;
+            }
+            "type" if target == ScanTarget::Keyspace => options.type_name = Some(keyword(value)),
+            _ => return Err(RespError::Syntax),
+        }
+    }
+    Ok(options)
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+fn scan(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let cursor = parse_cursor(&args[0])?;
+    let options = parse_scan_options(&args[1..], ScanTarget::Keyspace)?;
+    let (cursor, keys) = ctx.db.scan(cursor, options.count, |key, item| {
+        options.matches(key)
+            && options
+                .type_name
+                .as_ref()
+                .is_none_or(|type_name| item.type_name() == type_name)
+    });
+    Ok(Value::Array(vec![
+        bulk(cursor.to_string()),
+        Value::Array(keys.into_iter().map(Value::BulkString).collect()),
+    ]))
+}
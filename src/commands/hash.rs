@@ -0,0 +1,753 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{
+    bulk,
+    generic::{parse_cursor, parse_scan_options, unix_millis, ExpireBase, ScanTarget},
+    keyword, ok, parse_random_count,
+    string::{parse_f64, parse_i64},
+    CommandSpec, Context, MAX_RANDOM_REPEATS,
+};
+use crate::{
+    random::random_index,
+    resp::{
+        resp::{Protocol, Value},
+        RespError,
+    },
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "hset",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Creates or modifies the value of a field in a hash.",
+        since: "2.0.0",
+        handler: hset,
+    },
+    CommandSpec {
+        name: "hmset",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Sets the values of multiple fields.",
+        since: "2.0.0",
+        handler: hmset,
+    },
+    CommandSpec {
+        name: "hsetnx",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Sets the value of a field in a hash only when the field doesn't exist.",
+        since: "2.0.0",
+        handler: hsetnx,
+    },
+    CommandSpec {
+        name: "hget",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the value of a field in a hash.",
+        since: "2.0.0",
+        handler: hget,
+    },
+    CommandSpec {
+        name: "hmget",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the values of all fields in a hash.",
+        since: "2.0.0",
+        handler: hmget,
+    },
+    CommandSpec {
+        name: "hdel",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+        since: "2.0.0",
+        handler: hdel,
+    },
+    CommandSpec {
+        name: "hlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the number of fields in a hash.",
+        since: "2.0.0",
+        handler: hlen,
+    },
+    CommandSpec {
+        name: "hstrlen",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the length of the value of a field.",
+        since: "3.2.0",
+        handler: hstrlen,
+    },
+    CommandSpec {
+        name: "hexists",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Determines whether a field exists in a hash.",
+        since: "2.0.0",
+        handler: hexists,
+    },
+    CommandSpec {
+        name: "hkeys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns all fields in a hash.",
+        since: "2.0.0",
+        handler: hkeys,
+    },
+    CommandSpec {
+        name: "hvals",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns all values in a hash.",
+        since: "2.0.0",
+        handler: hvals,
+    },
+    CommandSpec {
+        name: "hgetall",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns all fields and values in a hash.",
+        since: "2.0.0",
+        handler: hgetall,
+    },
+    CommandSpec {
+        name: "hincrby",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.",
+        since: "2.0.0",
+        handler: hincrby,
+    },
+    CommandSpec {
+        name: "hincrbyfloat",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.",
+        since: "2.6.0",
+        handler: hincrbyfloat,
+    },
+    CommandSpec {
+        name: "hrandfield",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns one or more random fields from a hash.",
+        since: "6.2.0",
+        handler: hrandfield,
+    },
+    CommandSpec {
+        name: "hscan",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Iterates over fields and values of a hash.",
+        since: "2.8.0",
+        handler: hscan,
+    },
+    CommandSpec {
+        name: "hexpire",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Set expiry for hash field using relative time to expire (seconds)",
+        since: "7.4.0",
+        handler: hexpire,
+    },
+    CommandSpec {
+        name: "hpexpire",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Set expiry for hash field using relative time to expire (milliseconds)",
+        since: "7.4.0",
+        handler: hpexpire,
+    },
+    CommandSpec {
+        name: "hexpireat",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (seconds)",
+        since: "7.4.0",
+        handler: hexpireat,
+    },
+    CommandSpec {
+        name: "hpexpireat",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (milliseconds)",
+        since: "7.4.0",
+        handler: hpexpireat,
+    },
+    CommandSpec {
+        name: "httl",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the TTL in seconds of a hash field.",
+        since: "7.4.0",
+        handler: httl,
+    },
+    CommandSpec {
+        name: "hpttl",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the TTL in milliseconds of a hash field.",
+        since: "7.4.0",
+        handler: hpttl,
+    },
+    CommandSpec {
+        name: "hexpiretime",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the expiration time of a hash field as a Unix timestamp, in seconds.",
+        since: "7.4.0",
+        handler: hexpiretime,
+    },
+    CommandSpec {
+        name: "hpexpiretime",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Returns the expiration time of a hash field as a Unix timestamp, in msec.",
+        since: "7.4.0",
+        handler: hpexpiretime,
+    },
+    CommandSpec {
+        name: "hpersist",
+        arity: -5,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "hash",
+        summary: "Removes the expiration time for each specified field",
+        since: "7.4.0",
+        handler: hpersist,
+    },
+];
+
+/// Sets each `field value` pair of `args`, returning how many fields are new.
+fn set_fields(
+    ctx: &mut Context,
+    key: &Bytes,
+    pairs: &[Bytes],
+    command: &str,
+) -> Result<i64, RespError> {
+    if pairs.len() % 2 == 1 {
+        return Err(RespError::WrongArity(command.to_owned()));
+    }
+    let limits = ctx.config.hash_limits();
+    let hash = ctx.db.get_or_create_hash(key)?;
+    let mut added = 0;
+    for pair in pairs.chunks(2) {
+        added += hash.insert(pair[0].clone(), pair[1].clone(), limits) as i64;
+    }
+    Ok(added)
+}
+
+/// `HSET key field value [field value ...]`
+fn hset(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    set_fields(ctx, &args[0], &args[1..], "hset").map(Value::Integer)
+}
+
+fn hmset(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    set_fields(ctx, &args[0], &args[1..], "hmset").map(|_| ok())
+}
+
+fn hsetnx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let limits = ctx.config.hash_limits();
+    let hash = ctx.db.get_or_create_hash(&args[0])?;
+    if hash.get(&args[1]).is_some() {
+        return Ok(Value::Integer(0));
+    }
+    hash.insert(args[1].clone(), args[2].clone(), limits);
+    Ok(Value::Integer(1))
+}
+
+fn hget(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let value = ctx
+        .db
+        .get_hash(&args[0])?
+        .and_then(|hash| hash.get(&args[1]).cloned());
+    Ok(value.map_or(Value::Null, bulk))
+}
+
+fn hmget(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let hash = ctx.db.get_hash(&args[0])?;
+    Ok(Value::Array(
+        args[1..]
+            .iter()
+            .map(|field| {
+                hash.as_ref()
+                    .and_then(|hash| hash.get(field).cloned())
+                    .map_or(Value::Null, bulk)
+            })
+            .collect(),
+    ))
+}
+
+fn hdel(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let Some(hash) = ctx.db.get_hash(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let removed = args[1..].iter().filter(|field| hash.remove(field)).count();
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(Value::Integer(removed as i64))
+}
+
+fn hlen(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx.db.get_hash(&args[0])?.map_or(0, |hash| hash.len());
+    Ok(Value::Integer(len as i64))
+}
+
+fn hstrlen(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx
+        .db
+        .get_hash(&args[0])?
+        .and_then(|hash| hash.get(&args[1]).map(|value| value.len()))
+        .unwrap_or(0);
+    Ok(Value::Integer(len as i64))
+}
+
+fn hexists(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let exists = ctx
+        .db
+        .get_hash(&args[0])?
+        .is_some_and(|hash| hash.get(&args[1]).is_some());
+    Ok(Value::Integer(exists as i64))
+}
+
+fn hkeys(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let fields = ctx.db.get_hash(&args[0])?.map_or_else(Vec::new, |hash| {
+        hash.iter().map(|(field, _)| bulk(field.clone())).collect()
+    });
+    Ok(Value::Array(fields))
+}
+
+fn hvals(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let values = ctx.db.get_hash(&args[0])?.map_or_else(Vec::new, |hash| {
+        hash.iter().map(|(_, value)| bulk(value.clone())).collect()
+    });
+    Ok(Value::Array(values))
+}
+
+fn hgetall(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let pairs = ctx.db.get_hash(&args[0])?.map_or_else(Vec::new, |hash| {
+        hash.iter()
+            .map(|(field, value)| (bulk(field.clone()), bulk(value.clone())))
+            .collect()
+    });
+    Ok(Value::Map(pairs))
+}
+
+fn hincrby(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let delta = parse_i64(&args[2])?;
+    let limits = ctx.config.hash_limits();
+    let existing = ctx
+        .db
+        .get_hash(&args[0])?
+        .and_then(|hash| hash.get(&args[1]));
+    let current = match existing {
+        Some(value) => parse_i64(value)
+            .map_err(|_| RespError::Other("hash value is not an integer".to_owned()))?,
+        None => 0,
+    };
+    let updated = current
+        .checked_add(delta)
+        .ok_or_else(|| RespError::Other("increment or decrement would overflow".to_owned()))?;
+    // Only created once nothing can fail, so no empty hash is left behind.
+    let hash = ctx.db.get_or_create_hash(&args[0])?;
+    let ttl = hash.expire_at(&args[1]);
+    hash.insert(args[1].clone(), updated.to_string().into(), limits);
+    hash.set_expire(&args[1], ttl);
+    Ok(Value::Integer(updated))
+}
+
+fn hincrbyfloat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let delta = parse_f64(&args[2])?;
+    if !delta.is_finite() {
+        return Err(RespError::Other("value is NaN or Infinity".to_owned()));
+    }
+    let limits = ctx.config.hash_limits();
+    let existing = ctx
+        .db
+        .get_hash(&args[0])?
+        .and_then(|hash| hash.get(&args[1]));
+    let current = match existing {
+        Some(value) => parse_f64(value)
+            .map_err(|_| RespError::Other("hash value is not a float".to_owned()))?,
+        None => 0.0,
+    };
+    let updated = current + delta;
+    if !updated.is_finite() {
+        return Err(RespError::Other(
+            "increment would produce NaN or Infinity".to_owned(),
+        ));
+    }
+    let updated = Bytes::from(format!("{}", updated));
+    let hash = ctx.db.get_or_create_hash(&args[0])?;
+    let ttl = hash.expire_at(&args[1]);
+    hash.insert(args[1].clone(), updated.clone(), limits);
+    hash.set_expire(&args[1], ttl);
+    Ok(bulk(updated))
+}
+
+/// `HRANDFIELD key [count [WITHVALUES]]`
+fn hrandfield(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (count, with_values) = match &args[1..] {
+        [] => (None, false),
+        [count] => (Some(parse_random_count(count)?), false),
+        [count, option] if keyword(option) == "withvalues" => {
+            (Some(parse_random_count(count)?), true)
+        }
+        _ => return Err(RespError::Syntax),
+    };
+    let protocol = ctx.client.protocol;
+    let hash = ctx.db.get_hash(&args[0])?;
+    let Some(count) = count else {
+        return Ok(hash
+            .and_then(|hash| hash.random_entry())
+            .map_or(Value::Null, |(field, _)| bulk(field.clone())));
+    };
+    let Some(hash) = hash else {
+        return Ok(Value::Array(vec![]));
+    };
+
+    let picked: Vec<(Bytes, Bytes)> = if count < 0 {
+        // Repetitions allowed: draw independently.
+        (0..count.unsigned_abs().min(MAX_RANDOM_REPEATS))
+            .filter_map(|_| hash.random_entry())
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect()
+    } else {
+        // Distinct fields: a partial shuffle of all of them.
+        let mut all: Vec<(Bytes, Bytes)> = hash
+            .iter()
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        let count = (count as usize).min(all.len());
+        for i in 0..count {
+            let j = i + random_index(all.len() - i);
+            all.swap(i, j);
+        }
+        all.truncate(count);
+        all
+    };
+
+    Ok(Value::Array(if !with_values {
+        picked.into_iter().map(|(field, _)| bulk(field)).collect()
+    } else if protocol == Protocol::Resp3 {
+        picked
+            .into_iter()
+            .map(|(field, value)| Value::Array(vec![bulk(field), bulk(value)]))
+            .collect()
+    } else {
+        picked
+            .into_iter()
+            .flat_map(|(field, value)| [bulk(field), bulk(value)])
+            .collect()
+    }))
+}
+
+/// `HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]`
+fn hscan(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let cursor = parse_cursor(&args[1])?;
+    let options = parse_scan_options(&args[2..], ScanTarget::Hash)?;
+    let mut items = vec![];
+    let cursor = match ctx.db.get_hash(&args[0])? {
+        Some(hash) => hash.scan(cursor, options.count, |field, value| {
+            if options.matches(field) {
+                items.push(bulk(field.clone()));
+                if !options.novalues {
+                    items.push(bulk(value.clone()));
+                }
+            }
+        }),
+        None => 0,
+    };
+    Ok(Value::Array(vec![
+        bulk(cursor.to_string()),
+        Value::Array(items),
+    ]))
+}
+
+/// Largest field deadline Redis accepts, in milliseconds since the epoch.
+const MAX_FIELD_DEADLINE: i64 = (1 << 48) - 1;
+
+/// The optional `NX | XX | GT | LT` condition of the field TTL commands.
+#[derive(Clone, Copy)]
+enum Condition {
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+/// Parses the trailing `FIELDS numfields field [field ...]` of the field
+/// TTL commands.
+fn parse_fields(args: &[Bytes]) -> Result<&[Bytes], RespError> {
+    match args {
+        [keyword_arg, numfields, fields @ ..] if keyword(keyword_arg) == "fields" => {
+            let numfields = parse_i64(numfields)?;
+            if numfields <= 0 {
+                return Err(RespError::Other(
+                    "Parameter `numFields` should be greater than 0".to_owned(),
+                ));
+            }
+            if numfields as usize != fields.len() {
+                return Err(RespError::Other(
+                    "The `numfields` parameter must match the number of arguments".to_owned(),
+                ));
+            }
+            Ok(fields)
+        }
+        _ => Err(RespError::Other(
+            "Mandatory argument FIELDS is missing or not at the right position".to_owned(),
+        )),
+    }
+}
+
+/// Shared implementation of `HEXPIRE`, `HPEXPIRE`, `HEXPIREAT` and
+/// `HPEXPIREAT key time [NX | XX | GT | LT] FIELDS numfields field ...`.
+///
+/// Replies per field: -2 if it does not exist, 0 if the condition was not
+/// met, 1 if the TTL was set and 2 if the deadline has already passed and
+/// the field was deleted.
+fn hexpire_generic(
+    ctx: &mut Context,
+    args: &[Bytes],
+    base: ExpireBase,
+    command: &str,
+) -> Result<Value, RespError> {
+    let amount = parse_i64(&args[1])?;
+    let (condition, rest) = match keyword(&args[2]).as_str() {
+        "nx" => (Some(Condition::Nx), &args[3..]),
+        "xx" => (Some(Condition::Xx), &args[3..]),
+        "gt" => (Some(Condition::Gt), &args[3..]),
+        "lt" => (Some(Condition::Lt), &args[3..]),
+        _ => (None, &args[2..]),
+    };
+    let fields = parse_fields(rest)?;
+
+    let invalid = || RespError::Other(format!("invalid expire time in '{}' command", command));
+    let now = unix_millis(SystemTime::now());
+    let deadline = match base {
+        ExpireBase::Seconds => amount.checked_mul(1000).and_then(|ms| ms.checked_add(now)),
+        ExpireBase::Milliseconds => amount.checked_add(now),
+        ExpireBase::UnixSeconds => amount.checked_mul(1000),
+        ExpireBase::UnixMilliseconds => Some(amount),
+    }
+    .filter(|&deadline| amount >= 0 && deadline <= MAX_FIELD_DEADLINE)
+    .ok_or_else(invalid)?;
+
+    let Some(hash) = ctx.db.get_hash(&args[0])? else {
+        return Ok(Value::Array(vec![Value::Integer(-2); fields.len()]));
+    };
+    let mut replies = Vec::with_capacity(fields.len());
+    for field in fields {
+        if hash.get(field).is_none() {
+            replies.push(Value::Integer(-2));
+            continue;
+        }
+        let current = hash.expire_at(field).map(unix_millis);
+        let allowed = match (condition, current) {
+            (None, _) => true,
+            (Some(Condition::Nx), current) => current.is_none(),
+            // A field without a TTL counts as expiring infinitely far away.
+            (Some(Condition::Xx | Condition::Gt), None) => false,
+            (Some(Condition::Lt), None) => true,
+            (Some(Condition::Xx), Some(_)) => true,
+            (Some(Condition::Gt), Some(current)) => deadline > current,
+            (Some(Condition::Lt), Some(current)) => deadline < current,
+        };
+        if !allowed {
+            replies.push(Value::Integer(0));
+        } else if deadline <= now {
+            hash.remove(field);
+            replies.push(Value::Integer(2));
+        } else {
+            let deadline = UNIX_EPOCH + Duration::from_millis(deadline as u64);
+            hash.set_expire(field, Some(deadline));
+            replies.push(Value::Integer(1));
+        }
+    }
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(Value::Array(replies))
+}
+
+fn hexpire(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    hexpire_generic(ctx, args, ExpireBase::Seconds, "hexpire")
+}
+
+fn hpexpire(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    hexpire_generic(ctx, args, ExpireBase::Milliseconds, "hpexpire")
+}
+
+fn hexpireat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    hexpire_generic(ctx, args, ExpireBase::UnixSeconds, "hexpireat")
+}
+
+fn hpexpireat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    hexpire_generic(ctx, args, ExpireBase::UnixMilliseconds, "hpexpireat")
+}
+
+/// Replies per field with -2 for a missing field, -1 for a field without
+/// TTL, and otherwise its TTL converted by `convert` from (deadline, now)
+/// in milliseconds.
+fn httl_generic(
+    ctx: &mut Context,
+    args: &[Bytes],
+    convert: fn(i64, i64) -> i64,
+) -> Result<Value, RespError> {
+    let fields = parse_fields(&args[1..])?;
+    let now = unix_millis(SystemTime::now());
+    let hash = ctx.db.get_hash(&args[0])?;
+    Ok(Value::Array(
+        fields
+            .iter()
+            .map(|field| {
+                Value::Integer(match hash.as_ref() {
+                    Some(hash) if hash.get(field).is_some() => hash
+                        .expire_at(field)
+                        .map_or(-1, |deadline| convert(unix_millis(deadline), now)),
+                    _ => -2,
+                })
+            })
+            .collect(),
+    ))
+}
+
+fn httl(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    httl_generic(ctx, args, |deadline, now| {
+        ((deadline - now).max(0) + 500) / 1000
+    })
+}
+
+fn hpttl(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    httl_generic(ctx, args, |deadline, now| (deadline - now).max(0))
+}
+
+fn hexpiretime(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    httl_generic(ctx, args, |deadline, _| deadline / 1000)
+}
+
+fn hpexpiretime(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    httl_generic(ctx, args, |deadline, _| deadline)
+}
+
+/// `HPERSIST key FIELDS numfields field [field ...]`
+fn hpersist(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let fields = parse_fields(&args[1..])?;
+    let Some(hash) = ctx.db.get_hash(&args[0])? else {
+        return Ok(Value::Array(vec![Value::Integer(-2); fields.len()]));
+    };
+    Ok(Value::Array(
+        fields
+            .iter()
+            .map(|field| {
+                Value::Integer(if hash.get(field).is_none() {
+                    -2
+                } else if hash.expire_at(field).is_none() {
+                    -1
+                } else {
+                    hash.set_expire(field, None);
+                    1
+                })
+            })
+            .collect(),
+    ))
+}
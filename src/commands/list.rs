@@ -0,0 +1,732 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::{bulk, keyword, ok, string::parse_i64, CommandSpec, Context};
+use crate::{
+    blocking::parse_timeout,
+    resp::{resp::Value, RespError},
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "lpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        handler: lpush,
+    },
+    CommandSpec {
+        name: "rpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        handler: rpush,
+    },
+    CommandSpec {
+        name: "lpushx",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Prepends one or more elements to a list only when the list exists.",
+        since: "2.2.0",
+        handler: lpushx,
+    },
+    CommandSpec {
+        name: "rpushx",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Appends an element to a list only when the list exists.",
+        since: "2.2.0",
+        handler: rpushx,
+    },
+    CommandSpec {
+        name: "lpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+        since: "1.0.0",
+        handler: lpop,
+    },
+    CommandSpec {
+        name: "rpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns and removes the last elements of a list. Deletes the list if the last element was popped.",
+        since: "1.0.0",
+        handler: rpop,
+    },
+    CommandSpec {
+        name: "llen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns the length of a list.",
+        since: "1.0.0",
+        handler: llen,
+    },
+    CommandSpec {
+        name: "lindex",
+        arity: 3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns an element from a list by its index.",
+        since: "1.0.0",
+        handler: lindex,
+    },
+    CommandSpec {
+        name: "lset",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Sets the value of an element in a list by its index.",
+        since: "1.0.0",
+        handler: lset,
+    },
+    CommandSpec {
+        name: "lrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns a range of elements from a list.",
+        since: "1.0.0",
+        handler: lrange,
+    },
+    CommandSpec {
+        name: "ltrim",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Removes elements from both ends a list. Deletes the list if all elements were trimmed.",
+        since: "1.0.0",
+        handler: ltrim,
+    },
+    CommandSpec {
+        name: "lrem",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Removes elements from a list. Deletes the list if the last element was removed.",
+        since: "1.0.0",
+        handler: lrem,
+    },
+    CommandSpec {
+        name: "linsert",
+        arity: 5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Inserts an element before or after another element in a list.",
+        since: "2.2.0",
+        handler: linsert,
+    },
+    CommandSpec {
+        name: "lpos",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns the index of matching elements in a list.",
+        since: "6.0.6",
+        handler: lpos,
+    },
+    CommandSpec {
+        name: "lmove",
+        arity: 5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "list",
+        summary: "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.",
+        since: "6.2.0",
+        handler: lmove,
+    },
+    CommandSpec {
+        name: "rpoplpush",
+        arity: 3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "list",
+        summary: "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped.",
+        since: "1.2.0",
+        handler: rpoplpush,
+    },
+    CommandSpec {
+        name: "lmpop",
+        arity: -4,
+        flags: &["write", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "list",
+        summary: "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped.",
+        since: "7.0.0",
+        handler: lmpop,
+    },
+    CommandSpec {
+        name: "blpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        group: "list",
+        summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.0.0",
+        handler: blpop,
+    },
+    CommandSpec {
+        name: "brpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        group: "list",
+        summary: "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.0.0",
+        handler: brpop,
+    },
+    CommandSpec {
+        name: "blmove",
+        arity: 6,
+        flags: &["write", "denyoom", "blocking"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "list",
+        summary: "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.",
+        since: "6.2.0",
+        handler: blmove,
+    },
+    CommandSpec {
+        name: "brpoplpush",
+        arity: 4,
+        flags: &["write", "denyoom", "blocking"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "list",
+        summary: "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.2.0",
+        handler: brpoplpush,
+    },
+    CommandSpec {
+        name: "blmpop",
+        arity: -5,
+        flags: &["write", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "list",
+        summary: "Pops the first element from one of multiple lists. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "7.0.0",
+        handler: blmpop,
+    },
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    pub fn parse(arg: &[u8]) -> Result<Self, RespError> {
+        match keyword(arg).as_str() {
+            "left" => Ok(End::Left),
+            "right" => Ok(End::Right),
+            _ => Err(RespError::Syntax),
+        }
+    }
+}
+
+fn push_end(list: &mut VecDeque<Bytes>, end: End, element: Bytes) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+fn pop_end(list: &mut VecDeque<Bytes>, end: End) -> Option<Bytes> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+/// Resolves a possibly negative index against a list of `len` elements.
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Resolves an inclusive `start`/`stop` range like `LRANGE` does, or `None`
+/// if it selects nothing.
+pub(crate) fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+/// Parses the optional `count` of the pop commands, which must be positive.
+pub(crate) fn parse_count(arg: &[u8]) -> Result<usize, RespError> {
+    let count = parse_i64(arg)?;
+    if count < 0 {
+        return Err(RespError::Other(
+            "value is out of range, must be positive".to_owned(),
+        ));
+    }
+    Ok(count as usize)
+}
+
+fn push(
+    ctx: &mut Context,
+    args: &[Bytes],
+    end: End,
+    only_existing: bool,
+) -> Result<Value, RespError> {
+    let list = if only_existing {
+        match ctx.db.get_list(&args[0])? {
+            Some(list) => list,
+            None => return Ok(Value::Integer(0)),
+        }
+    } else {
+        ctx.db.get_or_create_list(&args[0])?
+    };
+    for element in &args[1..] {
+        push_end(list, end, element.clone());
+    }
+    Ok(Value::Integer(list.len() as i64))
+}
+
+fn lpush(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    push(ctx, args, End::Left, false)
+}
+
+fn rpush(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    push(ctx, args, End::Right, false)
+}
+
+fn lpushx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    push(ctx, args, End::Left, true)
+}
+
+fn rpushx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    push(ctx, args, End::Right, true)
+}
+
+/// Pops up to `count` elements from one end of the list at `key`, deleting
+/// the key once it is empty.
+pub(crate) fn pop_elements(
+    ctx: &mut Context,
+    key: &[u8],
+    end: End,
+    count: usize,
+) -> Result<Option<Vec<Bytes>>, RespError> {
+    let Some(list) = ctx.db.get_list(key)? else {
+        return Ok(None);
+    };
+    let popped = (0..count).map_while(|_| pop_end(list, end)).collect();
+    ctx.db.remove_if_empty(key);
+    Ok(Some(popped))
+}
+
+fn pop(ctx: &mut Context, args: &[Bytes], end: End) -> Result<Value, RespError> {
+    match args {
+        [key] => Ok(pop_elements(ctx, key, end, 1)?
+            .and_then(|popped| popped.into_iter().next())
+            .map_or(Value::Null, bulk)),
+        [key, count] => {
+            let count = parse_count(count)?;
+            Ok(
+                pop_elements(ctx, key, end, count)?.map_or(Value::NullArray, |popped| {
+                    Value::Array(popped.into_iter().map(bulk).collect())
+                }),
+            )
+        }
+        _ => Err(RespError::Syntax),
+    }
+}
+
+fn lpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    pop(ctx, args, End::Left)
+}
+
+fn rpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    pop(ctx, args, End::Right)
+}
+
+fn llen(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx.db.get_list(&args[0])?.map_or(0, |list| list.len());
+    Ok(Value::Integer(len as i64))
+}
+
+fn lindex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let index = parse_i64(&args[1])?;
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Ok(Value::Null);
+    };
+    Ok(resolve_index(index, list.len()).map_or(Value::Null, |index| bulk(list[index].clone())))
+}
+
+fn lset(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let index = parse_i64(&args[1])?;
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Err(RespError::Other("no such key".to_owned()));
+    };
+    let index = resolve_index(index, list.len())
+        .ok_or_else(|| RespError::Other("index out of range".to_owned()))?;
+    list[index] = args[2].clone();
+    Ok(ok())
+}
+
+fn lrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (start, stop) = (parse_i64(&args[1])?, parse_i64(&args[2])?);
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Ok(Value::Array(vec![]));
+    };
+    let Some((start, stop)) = resolve_range(start, stop, list.len()) else {
+        return Ok(Value::Array(vec![]));
+    };
+    Ok(Value::Array(
+        list.range(start..=stop).cloned().map(bulk).collect(),
+    ))
+}
+
+fn ltrim(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (start, stop) = (parse_i64(&args[1])?, parse_i64(&args[2])?);
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Ok(ok());
+    };
+    match resolve_range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(ok())
+}
+
+fn lrem(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let count = parse_i64(&args[1])?;
+    let element = &args[2];
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let limit = if count == 0 {
+        usize::MAX
+    } else {
+        count.unsigned_abs() as usize
+    };
+    // Removing from the tail is keeping all but the last `limit` matches.
+    let mut skip = if count < 0 {
+        let matches = list.iter().filter(|item| *item == element).count();
+        matches.saturating_sub(limit)
+    } else {
+        0
+    };
+    let mut removed = 0;
+    list.retain(|item| {
+        if item != element || removed == limit {
+            true
+        } else if skip > 0 {
+            skip -= 1;
+            true
+        } else {
+            removed += 1;
+            false
+        }
+    });
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(Value::Integer(removed as i64))
+}
+
+/// `LINSERT key BEFORE | AFTER pivot element`
+fn linsert(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let after = match keyword(&args[1]).as_str() {
+        "before" => false,
+        "after" => true,
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(list) = ctx.db.get_list(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let Some(pivot) = list.iter().position(|element| element == &args[2]) else {
+        return Ok(Value::Integer(-1));
+    };
+    list.insert(pivot + after as usize, args[3].clone());
+    Ok(Value::Integer(list.len() as i64))
+}
+
+/// `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`
+fn lpos(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (mut rank, mut count, mut maxlen) = (1i64, None, 0usize);
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = parse_i64(options.next().ok_or(RespError::Syntax)?)?;
+        match keyword(option).as_str() {
+            "rank" if value == 0 => {
+                return Err(RespError::Other("RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match".to_owned()))
+            }
+            "rank" if value == i64::MIN => {
+                return Err(RespError::Other(format!(
+                    "value is out of range, value must between {} and {}",
+                    -i64::MAX,
+                    i64::MAX
+                )))
+            }
+            "rank" => rank = value,
+            "count" if value < 0 => {
+                return Err(RespError::Other("COUNT can't be negative".to_owned()))
+            }
+            "count" => count = Some(value as usize),
+            "maxlen" if value < 0 => {
+                return Err(RespError::Other("MAXLEN can't be negative".to_owned()))
+            }
+            "maxlen" => maxlen = value as usize,
+            _ => return Err(RespError::Syntax),
+        }
+    }
+
+    let list = ctx.db.get_list(&args[0])?;
+    let empty = VecDeque::new();
+    let list = list.map_or(&empty, |list| &*list);
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+    let compared = if maxlen == 0 {
+        list.len()
+    } else {
+        maxlen.min(list.len())
+    };
+    let indexes: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..compared)
+    } else {
+        Box::new((list.len() - compared..list.len()).rev())
+    };
+    let matches: Vec<Value> = indexes
+        .filter(|&index| list[index] == args[1])
+        .skip(rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .map(|index| Value::Integer(index as i64))
+        .collect();
+
+    Ok(match count {
+        Some(_) => Value::Array(matches),
+        None => matches.into_iter().next().unwrap_or(Value::Null),
+    })
+}
+
+/// Pops from one end of `source` and pushes to one end of `destination`,
+/// which may be the same list.
+pub(crate) fn move_element(
+    ctx: &mut Context,
+    source: &Bytes,
+    destination: &Bytes,
+    from: End,
+    to: End,
+) -> Result<Option<Bytes>, RespError> {
+    // Check the destination type up front so a WRONGTYPE destination does
+    // not lose the popped element.
+    if ctx.db.get_list(source)?.is_none() {
+        return Ok(None);
+    }
+    ctx.db.get_list(destination)?;
+    let list = ctx
+        .db
+        .get_list(source)?
+        .expect("source list was just looked up");
+    let element = pop_end(list, from).expect("lists are never empty");
+    ctx.db.remove_if_empty(source);
+    push_end(ctx.db.get_or_create_list(destination)?, to, element.clone());
+    Ok(Some(element))
+}
+
+fn lmove(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (from, to) = (End::parse(&args[2])?, End::parse(&args[3])?);
+    Ok(move_element(ctx, &args[0], &args[1], from, to)?.map_or(Value::Null, bulk))
+}
+
+fn rpoplpush(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(move_element(ctx, &args[0], &args[1], End::Right, End::Left)?.map_or(Value::Null, bulk))
+}
+
+/// Parsed `numkeys key [key ...] <end> [COUNT count]` arguments of
+/// `LMPOP`/`BLMPOP`, where the end is `LEFT | RIGHT`, and of
+/// `ZMPOP`/`BZMPOP`, where it is `MIN | MAX`.
+pub(crate) struct MultiPop<E = End> {
+    pub keys: Vec<Bytes>,
+    pub end: E,
+    pub count: usize,
+}
+
+pub(crate) fn parse_multi_pop<E>(
+    args: &[Bytes],
+    parse_end: impl FnOnce(&[u8]) -> Result<E, RespError>,
+) -> Result<MultiPop<E>, RespError> {
+    let numkeys = parse_i64(&args[0])?;
+    if numkeys <= 0 {
+        return Err(RespError::Other(
+            "numkeys should be greater than 0".to_owned(),
+        ));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 1 {
+        return Err(RespError::Syntax);
+    }
+    let keys = args[1..=numkeys].to_vec();
+    let mut rest = args[numkeys + 1..].iter();
+    let end = parse_end(rest.next().ok_or(RespError::Syntax)?)?;
+    let count = match (rest.next(), rest.next(), rest.next()) {
+        (None, _, _) => 1,
+        (Some(option), Some(count), None) if keyword(option) == "count" => {
+            let count = parse_i64(count)?;
+            if count <= 0 {
+                return Err(RespError::Other(
+                    "count should be greater than 0".to_owned(),
+                ));
+            }
+            count as usize
+        }
+        _ => return Err(RespError::Syntax),
+    };
+    Ok(MultiPop { keys, end, count })
+}
+
+/// Pops from the first non-empty list among `keys`, replying with the key
+/// and the popped elements.
+pub(crate) fn multi_pop(ctx: &mut Context, request: &MultiPop) -> Result<Option<Value>, RespError> {
+    for key in &request.keys {
+        if let Some(popped) = pop_elements(ctx, key, request.end, request.count)? {
+            return Ok(Some(Value::Array(vec![
+                bulk(key.clone()),
+                Value::Array(popped.into_iter().map(bulk).collect()),
+            ])));
+        }
+    }
+    Ok(None)
+}
+
+fn lmpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let request = parse_multi_pop(args, End::parse)?;
+    Ok(multi_pop(ctx, &request)?.unwrap_or(Value::NullArray))
+}
+
+/// `BLPOP`/`BRPOP key [key ...] timeout`
+fn blocking_pop(ctx: &mut Context, args: &[Bytes], end: End) -> Result<Value, RespError> {
+    let (timeout, keys) = args.split_last().expect("arity checked");
+    let timeout = parse_timeout(timeout)?;
+    for key in keys {
+        if let Some(popped) = pop_elements(ctx, key, end, 1)? {
+            let element = popped.into_iter().next().expect("lists are never empty");
+            return Ok(Value::Array(vec![bulk(key.clone()), bulk(element)]));
+        }
+    }
+    Ok(ctx.block(keys.to_vec(), timeout, Value::NullArray))
+}
+
+fn blpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    blocking_pop(ctx, args, End::Left)
+}
+
+fn brpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    blocking_pop(ctx, args, End::Right)
+}
+
+fn blocking_move(
+    ctx: &mut Context,
+    args: &[Bytes],
+    from: End,
+    to: End,
+    timeout: &[u8],
+) -> Result<Value, RespError> {
+    let timeout = parse_timeout(timeout)?;
+    match move_element(ctx, &args[0], &args[1], from, to)? {
+        Some(element) => Ok(bulk(element)),
+        None => Ok(ctx.block(vec![args[0].clone()], timeout, Value::Null)),
+    }
+}
+
+/// `BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout`
+fn blmove(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (from, to) = (End::parse(&args[2])?, End::parse(&args[3])?);
+    blocking_move(ctx, args, from, to, &args[4])
+}
+
+fn brpoplpush(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    blocking_move(ctx, args, End::Right, End::Left, &args[2])
+}
+
+/// `BLMPOP timeout numkeys key [key ...] LEFT | RIGHT [COUNT count]`
+fn blmpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let timeout = parse_timeout(&args[0])?;
+    let request = parse_multi_pop(&args[1..], End::parse)?;
+    match multi_pop(ctx, &request)? {
+        Some(reply) => Ok(reply),
+        None => Ok(ctx.block(request.keys, timeout, Value::NullArray)),
+    }
+}
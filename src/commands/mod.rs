@@ -0,0 +1,391 @@
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use crate::{
+    blocking::BlockRequest,
+    config::Config,
+    resp::{resp::Value, RespError},
+    server::Client,
+    storage::Storage,
+};
+
+pub mod bitmap;
+pub mod connection;
+pub mod generic;
+pub mod hash;
+pub mod list;
+pub mod server;
+pub mod set;
+pub mod stream;
+pub mod string;
+pub mod zset;
+
+/// Everything a command handler may touch while it runs.
+pub struct Context<'a> {
+    pub db: &'a mut Storage,
+    pub config: &'a Config,
+    pub client: &'a mut Client,
+}
+
+impl Context<'_> {
+    /// Asks for the client to be parked until one of `keys` is written or
+    /// `timeout` passes, at which point it gets `timeout_reply`.
+    ///
+    /// Nothing is sent for the command itself: once a key is ready the whole
+    /// command runs again on the client's behalf, and may block again.
+    pub fn block(
+        &mut self,
+        keys: Vec<Bytes>,
+        timeout: Option<Duration>,
+        timeout_reply: Value,
+    ) -> Value {
+        self.client.block = Some(BlockRequest {
+            keys,
+            timeout,
+            timeout_reply,
+            retry_args: None,
+        });
+        Value::Null
+    }
+
+    /// Like [`Context::block`], but runs the command again with `args`
+    /// instead of its own, for arguments that stand for something as of the
+    /// time the command blocked (such as `XREAD`'s `$`).
+    pub fn block_with_args(
+        &mut self,
+        keys: Vec<Bytes>,
+        timeout: Option<Duration>,
+        timeout_reply: Value,
+        args: Vec<Bytes>,
+    ) -> Value {
+        self.block(keys, timeout, timeout_reply);
+        if let Some(request) = &mut self.client.block {
+            request.retry_args = Some(args);
+        }
+        Value::Null
+    }
+}
+
+/// Handlers receive the arguments after the command name; arity has already
+/// been checked against the table.
+pub type Handler = fn(&mut Context, &[Bytes]) -> Result<Value, RespError>;
+
+/// One entry of the command table, carrying the metadata `COMMAND` reports.
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Number of arguments including the command name. Negative values mean
+    /// "at least that many".
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    /// Position of the first key, the last key (negative counts from the
+    /// end) and the step between keys, like Redis' legacy key specs.
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    pub fn accepts(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity < 0 {
+            argc >= -self.arity
+        } else {
+            argc == self.arity
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+fn tables() -> [&'static [CommandSpec]; 10] {
+    [
+        bitmap::COMMANDS,
+        connection::COMMANDS,
+        generic::COMMANDS,
+        hash::COMMANDS,
+        list::COMMANDS,
+        server::COMMANDS,
+        set::COMMANDS,
+        stream::COMMANDS,
+        string::COMMANDS,
+        zset::COMMANDS,
+    ]
+}
+
+/// All registered commands, in table order.
+pub fn all() -> impl Iterator<Item = &'static CommandSpec> {
+    tables().into_iter().flatten()
+}
+
+/// Finds a command by name, case-insensitively.
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| all().map(|spec| (spec.name, spec)).collect());
+    let name = std::str::from_utf8(name).ok()?.to_ascii_lowercase();
+    table.get(name.as_str()).copied()
+}
+
+/// Looks up and runs one request (`argv[0]` is the command name).
+pub fn execute(ctx: &mut Context, argv: &[Bytes]) -> Result<Value, RespError> {
+    let Some(name) = argv.first() else {
+        return Err(RespError::Other("empty command".to_owned()));
+    };
+    let Some(spec) = lookup(name) else {
+        let args = argv[1..]
+            .iter()
+            .map(|arg| format!("'{}' ", String::from_utf8_lossy(arg)))
+            .collect::<String>();
+        return Err(RespError::Other(format!(
+            "unknown command '{}', with args beginning with: {}",
+            String::from_utf8_lossy(name),
+            args
+        )));
+    };
+    if !spec.accepts(argv.len()) {
+        return Err(RespError::WrongArity(spec.name.to_owned()));
+    }
+    (spec.handler)(ctx, &argv[1..])
+}
+
+/// Runs one request for a connection, then serves whoever it unblocked.
+///
+/// If the command asked to block, the client is parked before returning,
+/// while the caller still holds the database lock, so no write can slip in
+/// between and go unnoticed. The reply is then delivered on the returned
+/// receiver instead, and the caller waits on it up to the request's timeout.
+pub fn run(
+    db: &mut Storage,
+    config: &Config,
+    client: &mut Client,
+    argv: Vec<Bytes>,
+) -> (Value, Option<(BlockRequest, oneshot::Receiver<Value>)>) {
+    let mut ctx = Context { db, config, client };
+    let reply = execute(&mut ctx, &argv).unwrap_or_else(|e| e.to_value());
+    let parked = client.block.take().map(|mut request| {
+        let (sender, receiver) = oneshot::channel();
+        let argv = match request.retry_args.take() {
+            Some(args) => std::iter::once(argv[0].clone()).chain(args).collect(),
+            None => argv,
+        };
+        db.blocking
+            .block(client.clone(), argv, request.keys.clone(), sender);
+        (request, receiver)
+    });
+    serve_blocked_clients(db, config);
+    (reply, parked)
+}
+
+/// Runs again the commands of clients blocked on keys that were written,
+/// serving the clients of each key in the order they blocked.
+///
+/// Called after every command while the database is still locked, so a
+/// push is handed to a waiting client before anybody else can pop it. A
+/// served command may itself make other keys ready (as `BLMOVE` does), so
+/// this repeats until nothing is left.
+pub fn serve_blocked_clients(db: &mut Storage, config: &Config) {
+    loop {
+        let ready = db.blocking.take_ready();
+        if ready.is_empty() {
+            return;
+        }
+        for key in ready {
+            for id in db.blocking.waiters(&key) {
+                let Some(mut blocked) = db.blocking.take(id) else {
+                    continue;
+                };
+                let mut ctx = Context {
+                    db,
+                    config,
+                    client: &mut blocked.client,
+                };
+                let reply = execute(&mut ctx, &blocked.argv).unwrap_or_else(|e| e.to_value());
+                if blocked.client.block.take().is_some() {
+                    db.blocking.park_again(blocked);
+                } else {
+                    db.blocking.finish(blocked, reply);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn ok() -> Value {
+    Value::SimpleString("OK".to_owned())
+}
+
+pub(crate) fn bulk(bytes: impl Into<Bytes>) -> Value {
+    Value::BulkString(bytes.into())
+}
+
+/// Lowercased text form of an option argument, for matching keywords.
+pub(crate) fn keyword(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).to_ascii_lowercase()
+}
+
+/// Most elements drawn for a negative `HRANDFIELD`, `SRANDMEMBER` or
+/// `ZRANDMEMBER` count. Redis streams such replies; here they are built in
+/// memory first, so they are cut short rather than exhausting it.
+pub(crate) const MAX_RANDOM_REPEATS: u64 = 1024 * 1024;
+
+/// Parses the count of `HRANDFIELD`, `SRANDMEMBER` and `ZRANDMEMBER`, which
+/// like in Redis must stay within half the range of an integer.
+pub(crate) fn parse_random_count(arg: &[u8]) -> Result<i64, RespError> {
+    let count = string::parse_i64(arg)?;
+    if !(-(i64::MAX / 2)..=i64::MAX / 2).contains(&count) {
+        return Err(RespError::Other("value is out of range".to_owned()));
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::sync::oneshot;
+
+    use crate::{
+        config::Config,
+        resp::resp::{Protocol, Value},
+        server::Client,
+        storage::Storage,
+    };
+
+    fn argv(command: &str) -> Vec<Bytes> {
+        command
+            .split_whitespace()
+            .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+            .collect()
+    }
+
+    fn resp(value: Value) -> Vec<u8> {
+        value.serialize_with(Protocol::Resp2)
+    }
+
+    /// Runs `command` the way a connection does, returning its reply or,
+    /// if it blocked, where the reply will be delivered.
+    fn run(
+        db: &mut Storage,
+        config: &Config,
+        client: &mut Client,
+        command: &str,
+    ) -> Result<Value, oneshot::Receiver<Value>> {
+        match super::run(db, config, client, argv(command)) {
+            (reply, None) => Ok(reply),
+            (_, Some((_, receiver))) => Err(receiver),
+        }
+    }
+
+    fn setup() -> (Storage, Config) {
+        (Storage::new(), Config::parse_from(["redis-server"]))
+    }
+
+    #[test]
+    fn serves_waiters_in_the_order_they_blocked() {
+        let (mut db, config) = setup();
+        let (mut first, mut second) = (Client::new(), Client::new());
+        let mut first_reply = run(&mut db, &config, &mut first, "BLPOP q 0").unwrap_err();
+        let mut second_reply = run(&mut db, &config, &mut second, "BLPOP q 0").unwrap_err();
+
+        run(&mut db, &config, &mut Client::new(), "RPUSH q a b").unwrap();
+        assert_eq!(
+            resp(first_reply.try_recv().unwrap()),
+            b"*2\r\n$1\r\nq\r\n$1\r\na\r\n"
+        );
+        assert_eq!(
+            resp(second_reply.try_recv().unwrap()),
+            b"*2\r\n$1\r\nq\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(db.blocking.len(), 0);
+    }
+
+    #[test]
+    fn parks_again_a_waiter_left_without_data() {
+        let (mut db, config) = setup();
+        let (mut first, mut second) = (Client::new(), Client::new());
+        let mut first_reply = run(&mut db, &config, &mut first, "BLPOP q 0").unwrap_err();
+        let mut second_reply = run(&mut db, &config, &mut second, "BLPOP q 0").unwrap_err();
+
+        // The first waiter takes the only element, so the second one's
+        // retry blocks again and it keeps waiting.
+        run(&mut db, &config, &mut Client::new(), "RPUSH q a").unwrap();
+        assert!(first_reply.try_recv().is_ok());
+        assert!(second_reply.try_recv().is_err());
+        assert_eq!(db.blocking.len(), 1);
+
+        run(&mut db, &config, &mut Client::new(), "RPUSH q b").unwrap();
+        assert_eq!(
+            resp(second_reply.try_recv().unwrap()),
+            b"*2\r\n$1\r\nq\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(db.blocking.len(), 0);
+    }
+
+    #[test]
+    fn a_timeout_loses_to_an_earlier_serve() {
+        let (mut db, config) = setup();
+        let (mut served, mut timed_out) = (Client::new(), Client::new());
+        let mut served_reply = run(&mut db, &config, &mut served, "BLPOP q 1").unwrap_err();
+        let mut timed_out_reply = run(&mut db, &config, &mut timed_out, "BLPOP r 1").unwrap_err();
+
+        // Both time out, but `q` is written before the first one gets hold
+        // of the database again: it must still see the element it popped.
+        run(&mut db, &config, &mut Client::new(), "RPUSH q a").unwrap();
+        assert!(!db.blocking.unblock(served.id));
+        assert_eq!(
+            resp(served_reply.try_recv().unwrap()),
+            b"*2\r\n$1\r\nq\r\n$1\r\na\r\n"
+        );
+
+        // The other one really timed out, so a later write stays put.
+        assert!(db.blocking.unblock(timed_out.id));
+        run(&mut db, &config, &mut Client::new(), "RPUSH r b").unwrap();
+        assert!(timed_out_reply.try_recv().is_err());
+        let len = run(&mut db, &config, &mut Client::new(), "LLEN r").unwrap();
+        assert_eq!(resp(len), b":1\r\n");
+    }
+
+    #[test]
+    fn a_served_blmove_makes_its_destination_ready() {
+        let (mut db, config) = setup();
+        let (mut mover, mut popper) = (Client::new(), Client::new());
+        let mut mover_reply =
+            run(&mut db, &config, &mut mover, "BLMOVE src dst LEFT LEFT 0").unwrap_err();
+        let mut popper_reply = run(&mut db, &config, &mut popper, "BLPOP dst 0").unwrap_err();
+
+        run(&mut db, &config, &mut Client::new(), "RPUSH src x").unwrap();
+        assert_eq!(resp(mover_reply.try_recv().unwrap()), b"$1\r\nx\r\n");
+        assert_eq!(
+            resp(popper_reply.try_recv().unwrap()),
+            b"*2\r\n$3\r\ndst\r\n$1\r\nx\r\n"
+        );
+        assert!(db.lookup(b"src").is_none() && db.lookup(b"dst").is_none());
+    }
+
+    #[test]
+    fn group_readers_wake_up_when_their_group_goes_away() {
+        let (mut db, config) = setup();
+        let mut client = Client::new();
+        for key in ["s", "t"] {
+            let create = format!("XGROUP CREATE {} g $ MKSTREAM", key);
+            run(&mut db, &config, &mut client, &create).unwrap();
+        }
+        let (mut first, mut second) = (Client::new(), Client::new());
+        let read = |key| format!("XREADGROUP GROUP g c BLOCK 0 STREAMS {} >", key);
+        let mut first_reply = run(&mut db, &config, &mut first, &read("s")).unwrap_err();
+        let mut second_reply = run(&mut db, &config, &mut second, &read("t")).unwrap_err();
+
+        run(&mut db, &config, &mut client, "XGROUP DESTROY s g").unwrap();
+        run(&mut db, &config, &mut client, "DEL t").unwrap();
+        for reply in [&mut first_reply, &mut second_reply] {
+            assert!(resp(reply.try_recv().unwrap()).starts_with(b"-NOGROUP"));
+        }
+        assert_eq!(db.blocking.len(), 0);
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+
+use super::{bulk, keyword, ok, CommandSpec, Context};
+use crate::resp::{resp::Value, RespError};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "A container for server configuration commands.",
+        since: "2.0.0",
+        handler: config,
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns detailed information about all commands.",
+        since: "2.8.13",
+        handler: command,
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns information and statistics about the server.",
+        since: "1.0.0",
+        handler: info,
+    },
+];
+
+/// `CONFIG GET parameter [parameter ...]`
+fn config(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    match keyword(&args[0]).as_str() {
+        "get" if args.len() > 1 => {
+            let mut pairs = vec![];
+            for param in &args[1..] {
+                let value = match param.as_ref() {
+                    b"dir" => ctx.config.dir.clone(),
+                    b"dbfilename" => ctx.config.dbfilename.clone(),
+                    name => std::str::from_utf8(name)
+                        .ok()
+                        .and_then(|name| ctx.config.tunable(name))
+                        .map(|value| value.load(Ordering::Relaxed).to_string()),
+                };
+                if let Some(value) = value {
+                    pairs.push((bulk(param.clone()), bulk(value)));
+                }
+            }
+            Ok(Value::Map(pairs))
+        }
+        "get" => Err(RespError::WrongArity("config|get".to_owned())),
+        "set" if args.len() > 1 && args.len() % 2 == 1 => {
+            // Validate everything before changing anything. Parameters this
+            // server does not implement are accepted and ignored.
+            let mut updates = vec![];
+            for pair in args[1..].chunks(2) {
+                let Some(tunable) = ctx.config.tunable(&keyword(&pair[0])) else {
+                    continue;
+                };
+                let value = std::str::from_utf8(&pair[1])
+                    .ok()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .ok_or_else(|| {
+                        RespError::Other(format!(
+                            "CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+                            String::from_utf8_lossy(&pair[0])
+                        ))
+                    })?;
+                updates.push((tunable, value));
+            }
+            for (tunable, value) in updates {
+                tunable.store(value, Ordering::Relaxed);
+            }
+            Ok(ok())
+        }
+        "set" => Err(RespError::WrongArity("config|set".to_owned())),
+        _ => Ok(ok()),
+    }
+}
+
+/// `COMMAND [COUNT | LIST | INFO [name ...] | DOCS [name ...]]`
+fn command(_ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let Some(subcommand) = args.first() else {
+        return Ok(Value::Array(super::all().map(command_info).collect()));
+    };
+    let names = &args[1..];
+    Ok(match keyword(subcommand).as_str() {
+        "count" if names.is_empty() => Value::Integer(super::all().count() as i64),
+        "list" if names.is_empty() => {
+            Value::Array(super::all().map(|spec| bulk(spec.name)).collect())
+        }
+        "info" if names.is_empty() => Value::Array(super::all().map(command_info).collect()),
+        "info" => Value::Array(
+            names
+                .iter()
+                .map(|name| super::lookup(name).map_or(Value::Null, command_info))
+                .collect(),
+        ),
+        "docs" => {
+            let specs: Vec<&CommandSpec> = if names.is_empty() {
+                super::all().collect()
+            } else {
+                names
+                    .iter()
+                    .filter_map(|name| super::lookup(name))
+                    .collect()
+            };
+            Value::Map(
+                specs
+                    .into_iter()
+                    .map(|spec| (bulk(spec.name), command_docs(spec)))
+                    .collect(),
+            )
+        }
+        other => {
+            return Err(RespError::Other(format!(
+                "unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+                other
+            )))
+        }
+    })
+}
+
+fn command_info(spec: &CommandSpec) -> Value {
+    let flags = spec
+        .flags
+        .iter()
+        .map(|flag| Value::SimpleString(flag.to_string()))
+        .collect();
+    Value::Array(vec![
+        bulk(spec.name),
+        Value::Integer(spec.arity),
+        Value::Set(flags),
+        Value::Integer(spec.first_key),
+        Value::Integer(spec.last_key),
+        Value::Integer(spec.step),
+        Value::Set(acl_categories(spec)),
+        Value::Array(vec![]),
+        Value::Array(vec![]),
+        Value::Array(vec![]),
+    ])
+}
+
+fn acl_categories(spec: &CommandSpec) -> Vec<Value> {
+    let mut categories = vec![format!("@{}", spec.group)];
+    if spec.has_flag("write") {
+        categories.push("@write".to_owned());
+    }
+    if spec.has_flag("readonly") {
+        categories.push("@read".to_owned());
+    }
+    if spec.has_flag("admin") {
+        categories.extend(["@admin".to_owned(), "@dangerous".to_owned()]);
+    }
+    if spec.has_flag("fast") {
+        categories.push("@fast".to_owned());
+    } else {
+        categories.push("@slow".to_owned());
+    }
+    categories.into_iter().map(Value::SimpleString).collect()
+}
+
+fn command_docs(spec: &CommandSpec) -> Value {
+    Value::Map(vec![
+        (bulk("summary"), bulk(spec.summary)),
+        (bulk("since"), bulk(spec.since)),
+        (bulk("group"), bulk(spec.group)),
+    ])
+}
+
+/// `INFO [section ...]` with the `server`, `clients`, `stats` and `keyspace`
+/// sections.
+fn info(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let requested: Vec<String> = args.iter().map(|arg| keyword(arg)).collect();
+    let wants = |section: &str| {
+        requested.is_empty()
+            || requested
+                .iter()
+                .any(|r| r == section || r == "all" || r == "default" || r == "everything")
+    };
+
+    let mut sections = vec![];
+    if wants("server") {
+        sections.push(format!(
+            "# Server\r\nredis_version:7.4.0\r\nredis_mode:standalone\r\nprocess_id:{}\r\ntcp_port:{}\r\n",
+            std::process::id(),
+            ctx.config.port.as_deref().unwrap_or("6379"),
+        ));
+    }
+    if wants("clients") {
+        sections.push(format!(
+            "# Clients\r\nblocked_clients:{}\r\n",
+            ctx.db.blocking.len()
+        ));
+    }
+    if wants("stats") {
+        sections.push(format!(
+            "# Stats\r\nexpired_keys:{}\r\nexpired_subkeys:{}\r\nexpired_time_cap_reached_count:{}\r\n",
+            ctx.db.stats.expired_keys,
+            ctx.db.stats.expired_subkeys,
+            ctx.db.stats.expired_time_cap_reached_count,
+        ));
+    }
+    if wants("keyspace") {
+        let mut section = "# Keyspace\r\n".to_owned();
+        if ctx.db.len() > 0 {
+            section.push_str(&format!(
+                "db0:keys={},expires={},avg_ttl=0\r\n",
+                ctx.db.len(),
+                ctx.db.expires_count(),
+            ));
+        }
+        sections.push(section);
+    }
+    Ok(Value::VerbatimString(
+        "txt".to_owned(),
+        sections.join("\r\n").into(),
+    ))
+}
@@ -0,0 +1,498 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use super::{
+    bulk,
+    generic::{parse_cursor, parse_scan_options, ScanTarget},
+    keyword,
+    list::parse_count,
+    parse_random_count,
+    string::parse_i64,
+    CommandSpec, Context, MAX_RANDOM_REPEATS,
+};
+use crate::{
+    resp::{resp::Value, RespError},
+    set::Set,
+    storage::{Item, RedisValue},
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "sadd",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Adds one or more members to a set. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        handler: sadd,
+    },
+    CommandSpec {
+        name: "srem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Removes one or more members from a set. Deletes the set if the last member was removed.",
+        since: "1.0.0",
+        handler: srem,
+    },
+    CommandSpec {
+        name: "smembers",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Returns all members of a set.",
+        since: "1.0.0",
+        handler: smembers,
+    },
+    CommandSpec {
+        name: "sismember",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Determines whether a member belongs to a set.",
+        since: "1.0.0",
+        handler: sismember,
+    },
+    CommandSpec {
+        name: "smismember",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Determines whether multiple members belong to a set.",
+        since: "6.2.0",
+        handler: smismember,
+    },
+    CommandSpec {
+        name: "scard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Returns the number of members in a set.",
+        since: "1.0.0",
+        handler: scard,
+    },
+    CommandSpec {
+        name: "spop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.",
+        since: "1.0.0",
+        handler: spop,
+    },
+    CommandSpec {
+        name: "srandmember",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Get one or multiple random members from a set",
+        since: "1.0.0",
+        handler: srandmember,
+    },
+    CommandSpec {
+        name: "smove",
+        arity: 4,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "set",
+        summary: "Moves a member from one set to another.",
+        since: "1.0.0",
+        handler: smove,
+    },
+    CommandSpec {
+        name: "sinter",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Returns the intersect of multiple sets.",
+        since: "1.0.0",
+        handler: sinter,
+    },
+    CommandSpec {
+        name: "sintercard",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "set",
+        summary: "Returns the number of members of the intersect of multiple sets.",
+        since: "7.0.0",
+        handler: sintercard,
+    },
+    CommandSpec {
+        name: "sinterstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Stores the intersect of multiple sets in a key.",
+        since: "1.0.0",
+        handler: sinterstore,
+    },
+    CommandSpec {
+        name: "sunion",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Returns the union of multiple sets.",
+        since: "1.0.0",
+        handler: sunion,
+    },
+    CommandSpec {
+        name: "sunionstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Stores the union of multiple sets in a key.",
+        since: "1.0.0",
+        handler: sunionstore,
+    },
+    CommandSpec {
+        name: "sdiff",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Returns the difference of multiple sets.",
+        since: "1.0.0",
+        handler: sdiff,
+    },
+    CommandSpec {
+        name: "sdiffstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "set",
+        summary: "Stores the difference of multiple sets in a key.",
+        since: "1.0.0",
+        handler: sdiffstore,
+    },
+    CommandSpec {
+        name: "sscan",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "set",
+        summary: "Iterates over members of a set.",
+        since: "2.0.0",
+        handler: sscan,
+    },
+];
+
+fn members_reply(members: impl IntoIterator<Item = Bytes>) -> Value {
+    Value::Set(members.into_iter().map(bulk).collect())
+}
+
+fn sadd(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let max_intset = ctx.config.set_max_intset();
+    let set = ctx.db.get_or_create_set(&args[0])?;
+    let added = args[1..]
+        .iter()
+        .filter(|member| set.insert((*member).clone(), max_intset))
+        .count();
+    Ok(Value::Integer(added as i64))
+}
+
+fn srem(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let Some(set) = ctx.db.get_set(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let removed = args[1..].iter().filter(|member| set.remove(member)).count();
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(Value::Integer(removed as i64))
+}
+
+fn smembers(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let set = ctx.db.get_set(&args[0])?;
+    Ok(members_reply(set.into_iter().flat_map(|set| set.iter())))
+}
+
+fn sismember(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let found = ctx
+        .db
+        .get_set(&args[0])?
+        .is_some_and(|set| set.contains(&args[1]));
+    Ok(Value::Integer(found as i64))
+}
+
+fn smismember(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let set = ctx.db.get_set(&args[0])?;
+    Ok(Value::Array(
+        args[1..]
+            .iter()
+            .map(|member| {
+                let found = set.as_ref().is_some_and(|set| set.contains(member));
+                Value::Integer(found as i64)
+            })
+            .collect(),
+    ))
+}
+
+fn scard(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx.db.get_set(&args[0])?.map_or(0, |set| set.len());
+    Ok(Value::Integer(len as i64))
+}
+
+/// `SPOP key [count]`
+fn spop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let count = match &args[1..] {
+        [] => None,
+        [count] => Some(parse_count(count)?),
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(set) = ctx.db.get_set(&args[0])? else {
+        return Ok(count.map_or(Value::Null, |_| Value::Set(vec![])));
+    };
+    let Some(count) = count else {
+        let popped = set.random_member();
+        if let Some(member) = &popped {
+            set.remove(member);
+        }
+        ctx.db.remove_if_empty(&args[0]);
+        return Ok(popped.map_or(Value::Null, bulk));
+    };
+    let popped = set.random_distinct(count);
+    for member in &popped {
+        set.remove(member);
+    }
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(members_reply(popped))
+}
+
+/// `SRANDMEMBER key [count]`
+fn srandmember(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let count = match &args[1..] {
+        [] => None,
+        [count] => Some(parse_random_count(count)?),
+        _ => return Err(RespError::Syntax),
+    };
+    let set = ctx.db.get_set(&args[0])?;
+    let Some(count) = count else {
+        return Ok(set
+            .and_then(|set| set.random_member())
+            .map_or(Value::Null, bulk));
+    };
+    let Some(set) = set else {
+        return Ok(Value::Array(vec![]));
+    };
+    let members = if count < 0 {
+        // Repetitions allowed: draw independently.
+        (0..count.unsigned_abs().min(MAX_RANDOM_REPEATS))
+            .filter_map(|_| set.random_member())
+            .collect()
+    } else {
+        set.random_distinct(count as usize)
+    };
+    Ok(Value::Array(members.into_iter().map(bulk).collect()))
+}
+
+/// `SMOVE source destination member`
+fn smove(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (source, destination, member) = (&args[0], &args[1], &args[2]);
+    // Check the destination type before touching the source.
+    ctx.db.get_set(destination)?;
+    let Some(set) = ctx.db.get_set(source)? else {
+        return Ok(Value::Integer(0));
+    };
+    if source == destination {
+        return Ok(Value::Integer(set.contains(member) as i64));
+    }
+    if !set.remove(member) {
+        return Ok(Value::Integer(0));
+    }
+    ctx.db.remove_if_empty(source);
+    let max_intset = ctx.config.set_max_intset();
+    ctx.db
+        .get_or_create_set(destination)?
+        .insert(member.clone(), max_intset);
+    Ok(Value::Integer(1))
+}
+
+#[derive(Clone, Copy)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Combines the sets at `keys`; missing keys count as empty sets.
+fn combine(ctx: &mut Context, keys: &[Bytes], op: SetOp) -> Result<Vec<Bytes>, RespError> {
+    let sets = ctx.db.get_sets(keys)?;
+    Ok(match op {
+        SetOp::Inter => {
+            let Some(mut sets) = sets.into_iter().collect::<Option<Vec<&Set>>>() else {
+                return Ok(vec![]);
+            };
+            // Walk the smallest set, probing the others.
+            sets.sort_by_key(|set| set.len());
+            sets[0]
+                .iter()
+                .filter(|member| sets[1..].iter().all(|set| set.contains(member)))
+                .collect()
+        }
+        SetOp::Union => {
+            let mut seen = HashSet::new();
+            sets.into_iter()
+                .flatten()
+                .flat_map(|set| set.iter())
+                .filter(|member| seen.insert(member.clone()))
+                .collect()
+        }
+        SetOp::Diff => {
+            let Some(first) = sets[0] else {
+                return Ok(vec![]);
+            };
+            first
+                .iter()
+                .filter(|member| sets[1..].iter().flatten().all(|set| !set.contains(member)))
+                .collect()
+        }
+    })
+}
+
+/// Replaces `destination` with a set of `members`, deleting it if there are
+/// none, and replies with the resulting size.
+fn store(ctx: &mut Context, destination: &Bytes, members: Vec<Bytes>) -> Value {
+    let len = members.len();
+    ctx.db.remove(destination);
+    if len > 0 {
+        let set = Set::from_members(members, ctx.config.set_max_intset());
+        ctx.db
+            .insert(destination.clone(), Item::new(RedisValue::Set(set)));
+    }
+    Value::Integer(len as i64)
+}
+
+fn sinter(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine(ctx, args, SetOp::Inter).map(members_reply)
+}
+
+fn sinterstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let members = combine(ctx, &args[1..], SetOp::Inter)?;
+    Ok(store(ctx, &args[0], members))
+}
+
+fn sunion(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine(ctx, args, SetOp::Union).map(members_reply)
+}
+
+fn sunionstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let members = combine(ctx, &args[1..], SetOp::Union)?;
+    Ok(store(ctx, &args[0], members))
+}
+
+fn sdiff(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine(ctx, args, SetOp::Diff).map(members_reply)
+}
+
+fn sdiffstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let members = combine(ctx, &args[1..], SetOp::Diff)?;
+    Ok(store(ctx, &args[0], members))
+}
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`
+fn sintercard(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let numkeys = parse_i64(&args[0])?;
+    if numkeys <= 0 {
+        return Err(RespError::Other(
+            "numkeys should be greater than 0".to_owned(),
+        ));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 1 {
+        return Err(RespError::Other(
+            "Number of keys can't be greater than number of args".to_owned(),
+        ));
+    }
+    let keys = &args[1..=numkeys];
+    let limit = match &args[numkeys + 1..] {
+        [] => 0,
+        [option, limit] if keyword(option) == "limit" => {
+            let limit = parse_i64(limit)?;
+            if limit < 0 {
+                return Err(RespError::Other("LIMIT can't be negative".to_owned()));
+            }
+            limit as usize
+        }
+        _ => return Err(RespError::Syntax),
+    };
+
+    let sets = ctx.db.get_sets(keys)?;
+    let Some(mut sets) = sets.into_iter().collect::<Option<Vec<&Set>>>() else {
+        return Ok(Value::Integer(0));
+    };
+    sets.sort_by_key(|set| set.len());
+    let mut matches = sets[0]
+        .iter()
+        .filter(|member| sets[1..].iter().all(|set| set.contains(member)));
+    // A limit of 0 means no limit; stop counting as soon as it is reached.
+    let count = match limit {
+        0 => matches.count(),
+        limit => matches.by_ref().take(limit).count(),
+    };
+    Ok(Value::Integer(count as i64))
+}
+
+/// `SSCAN key cursor [MATCH pattern] [COUNT count]`
+fn sscan(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let cursor = parse_cursor(&args[1])?;
+    let options = parse_scan_options(&args[2..], ScanTarget::Members)?;
+    let mut members = vec![];
+    let cursor = match ctx.db.get_set(&args[0])? {
+        Some(set) => set.scan(cursor, options.count, |member| {
+            if options.matches(&member) {
+                members.push(bulk(member));
+            }
+        }),
+        None => 0,
+    };
+    Ok(Value::Array(vec![
+        bulk(cursor.to_string()),
+        Value::Array(members),
+    ]))
+}
@@ -0,0 +1,1386 @@
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+
+use super::{bulk, generic::unix_millis, keyword, ok, string::parse_i64, CommandSpec, Context};
+use crate::{
+    resp::{
+        resp::{Protocol, Value},
+        RespError,
+    },
+    storage::Storage,
+    stream::{ConsumerGroup, Entry, Stream, StreamId, Threshold, Trim},
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "xadd",
+        arity: -5,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+        since: "5.0.0",
+        handler: xadd,
+    },
+    CommandSpec {
+        name: "xrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Returns the messages from a stream within a range of IDs.",
+        since: "5.0.0",
+        handler: xrange,
+    },
+    CommandSpec {
+        name: "xrevrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Returns the messages from a stream within a range of IDs in reverse order.",
+        since: "5.0.0",
+        handler: xrevrange,
+    },
+    CommandSpec {
+        name: "xlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Return the number of messages in a stream.",
+        since: "5.0.0",
+        handler: xlen,
+    },
+    CommandSpec {
+        name: "xdel",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Returns the number of messages after removing them from a stream.",
+        since: "5.0.0",
+        handler: xdel,
+    },
+    CommandSpec {
+        name: "xtrim",
+        arity: -4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Deletes messages from the beginning of a stream.",
+        since: "5.0.0",
+        handler: xtrim,
+    },
+    CommandSpec {
+        name: "xread",
+        arity: -4,
+        flags: &["readonly", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "stream",
+        summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        handler: xread,
+    },
+    CommandSpec {
+        name: "xgroup",
+        arity: -2,
+        flags: &["write", "denyoom"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        group: "stream",
+        summary: "A container for consumer groups commands.",
+        since: "5.0.0",
+        handler: xgroup,
+    },
+    CommandSpec {
+        name: "xreadgroup",
+        arity: -7,
+        flags: &["write", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "stream",
+        summary: "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        handler: xreadgroup,
+    },
+    CommandSpec {
+        name: "xack",
+        arity: -4,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.",
+        since: "5.0.0",
+        handler: xack,
+    },
+    CommandSpec {
+        name: "xpending",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Returns the information and entries from a stream consumer group's pending entries list.",
+        since: "5.0.0",
+        handler: xpending,
+    },
+    CommandSpec {
+        name: "xclaim",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member.",
+        since: "5.0.0",
+        handler: xclaim,
+    },
+    CommandSpec {
+        name: "xautoclaim",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "stream",
+        summary: "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to as consumer group member.",
+        since: "6.2.0",
+        handler: xautoclaim,
+    },
+    CommandSpec {
+        name: "xinfo",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        group: "stream",
+        summary: "A container for stream introspection commands.",
+        since: "5.0.0",
+        handler: xinfo,
+    },
+];
+
+fn invalid_id() -> RespError {
+    RespError::Other("Invalid stream ID specified as stream command argument".to_owned())
+}
+
+/// Parses an entry ID argument, where a bare timestamp takes `missing_seq`
+/// as its sequence.
+pub(crate) fn parse_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, RespError> {
+    StreamId::parse(arg, missing_seq).ok_or_else(invalid_id)
+}
+
+/// Replies with an entry as `[id, [field, value, ...]]`.
+pub(crate) fn entry_reply(entry: &Entry) -> Value {
+    Value::Array(vec![
+        bulk(entry.id.to_string()),
+        Value::Array(
+            entry
+                .fields
+                .iter()
+                .flat_map(|(field, value)| [bulk(field.clone()), bulk(value.clone())])
+                .collect(),
+        ),
+    ])
+}
+
+/// The trimming options `XADD` and `XTRIM` share:
+/// `MAXLEN | MINID [= | ~] threshold [LIMIT count]`.
+#[derive(Default)]
+struct TrimOptions {
+    threshold: Option<Threshold>,
+    approx: bool,
+    limit: Option<usize>,
+}
+
+impl TrimOptions {
+    /// Parses the option starting at `args[0]`, returning how many
+    /// arguments it took.
+    fn parse(&mut self, args: &[Bytes]) -> Result<usize, RespError> {
+        let value = |i: usize| args.get(i).ok_or(RespError::Syntax);
+        let option = keyword(&args[0]);
+        if option == "limit" {
+            let limit = parse_i64(value(1)?)?;
+            if limit < 0 {
+                return Err(RespError::Other(
+                    "The LIMIT argument must be >= 0.".to_owned(),
+                ));
+            }
+            self.limit = Some(limit as usize);
+            return Ok(2);
+        }
+        let mut used = 1;
+        self.approx = false;
+        match args.get(1).map(|arg| arg.as_ref()) {
+            Some(b"~") => {
+                self.approx = true;
+                used += 1;
+            }
+            Some(b"=") => used += 1,
+            _ => {}
+        }
+        let threshold = value(used)?;
+        self.threshold = Some(if option == "maxlen" {
+            let max = parse_i64(threshold)?;
+            if max < 0 {
+                return Err(RespError::Other(
+                    "The MAXLEN argument must be >= 0.".to_owned(),
+                ));
+            }
+            Threshold::MaxLen(max as usize)
+        } else {
+            Threshold::MinId(parse_id(threshold, 0)?)
+        });
+        Ok(used + 1)
+    }
+
+    /// The trim to apply, if any. An approximate trim without an explicit
+    /// `LIMIT` stops after 100 nodes' worth of entries, and `LIMIT 0` means
+    /// no limit.
+    fn finish(self, node_max_entries: usize) -> Result<Option<Trim>, RespError> {
+        if self.limit.is_some() && !self.approx {
+            return Err(RespError::Other(
+                "syntax error, LIMIT cannot be used without the special ~ option".to_owned(),
+            ));
+        }
+        let limit = match self.limit {
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None if self.approx => Some(100 * node_max_entries),
+            None => None,
+        };
+        Ok(self.threshold.map(|threshold| Trim {
+            threshold,
+            approx: self.approx,
+            limit,
+        }))
+    }
+}
+
+/// The ID argument of `XADD`.
+enum NewId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl NewId {
+    fn parse(arg: &[u8]) -> Result<Self, RespError> {
+        if arg == b"*" {
+            return Ok(NewId::Auto);
+        }
+        if let Some(ms) = arg.strip_suffix(b"-*") {
+            let ms = StreamId::parse_part(ms).ok_or_else(invalid_id)?;
+            return Ok(NewId::AutoSeq(ms));
+        }
+        let id = parse_id(arg, 0)?;
+        if id == StreamId::MIN {
+            return Err(RespError::Other(
+                "The ID specified in XADD must be greater than 0-0".to_owned(),
+            ));
+        }
+        Ok(NewId::Explicit(id))
+    }
+
+    /// The ID to add to `stream`, which must be greater than its last one.
+    fn resolve(&self, stream: &Stream) -> Result<StreamId, RespError> {
+        let last = stream.last_id;
+        let too_small = || {
+            RespError::Other(
+                "The ID specified in XADD is equal or smaller than the target stream top item"
+                    .to_owned(),
+            )
+        };
+        match *self {
+            NewId::Auto => stream.next_auto_id(now_millis()).ok_or_else(|| {
+                RespError::Other(
+                    "The stream has exhausted the last possible ID, unable to add more items"
+                        .to_owned(),
+                )
+            }),
+            NewId::AutoSeq(ms) if ms > last.ms => Ok(StreamId::new(ms, 0)),
+            NewId::AutoSeq(ms) if ms == last.ms && last.seq < u64::MAX => {
+                Ok(StreamId::new(ms, last.seq + 1))
+            }
+            NewId::AutoSeq(_) => Err(too_small()),
+            NewId::Explicit(id) if id > last => Ok(id),
+            NewId::Explicit(_) => Err(too_small()),
+        }
+    }
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]]
+/// * | id field value [field value ...]`
+fn xadd(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let key = &args[0];
+    let mut nomkstream = false;
+    let mut trim = TrimOptions::default();
+    let mut i = 1;
+    while i < args.len() {
+        match keyword(&args[i]).as_str() {
+            "nomkstream" => {
+                nomkstream = true;
+                i += 1;
+            }
+            "maxlen" | "minid" | "limit" => i += trim.parse(&args[i..])?,
+            _ => break,
+        }
+    }
+    let node_max_entries = ctx.config.stream_node_max();
+    let trim = trim.finish(node_max_entries)?;
+    let Some((id, pairs)) = args[i..].split_first() else {
+        return Err(RespError::WrongArity("xadd".to_owned()));
+    };
+    if pairs.is_empty() || pairs.len() % 2 == 1 {
+        return Err(RespError::WrongArity("xadd".to_owned()));
+    }
+    let id = NewId::parse(id)?;
+
+    if nomkstream && ctx.db.get_stream(key)?.is_none() {
+        return Ok(Value::Null);
+    }
+    // Any ID is acceptable for a new stream, so creating it first is safe.
+    let stream = ctx.db.get_or_create_stream(key)?;
+    let id = id.resolve(stream)?;
+    let fields = pairs
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    stream.append(id, fields, node_max_entries);
+    if let Some(trim) = trim {
+        stream.trim(trim);
+    }
+    // Creating the stream signalled already, appending to one does not.
+    ctx.db.blocking.signal(key);
+    Ok(bulk(id.to_string()))
+}
+
+/// Parses an `XRANGE` bound: `-`, `+`, or an ID that a leading `(`
+/// excludes. A bare timestamp covers all of its sequence numbers.
+fn parse_range_bound(arg: &[u8], start: bool) -> Result<StreamId, RespError> {
+    match arg {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let missing_seq = if start { 0 } else { u64::MAX };
+    let Some(id) = arg.strip_prefix(b"(") else {
+        return parse_id(arg, missing_seq);
+    };
+    let id = parse_id(id, missing_seq)?;
+    let (bound, error) = if start {
+        (id.next(), "invalid start ID for the interval")
+    } else {
+        (id.prev(), "invalid end ID for the interval")
+    };
+    bound.ok_or_else(|| RespError::Other(error.to_owned()))
+}
+
+/// `XRANGE key start end [COUNT count]`, or `XREVRANGE key end start` if
+/// `rev`.
+fn range(ctx: &mut Context, args: &[Bytes], rev: bool) -> Result<Value, RespError> {
+    let (start, end) = if rev {
+        (&args[2], &args[1])
+    } else {
+        (&args[1], &args[2])
+    };
+    let (start, end) = (
+        parse_range_bound(start, true)?,
+        parse_range_bound(end, false)?,
+    );
+    let count = match &args[3..] {
+        [] => None,
+        [option, count] if keyword(option) == "count" => Some(parse_i64(count)?.max(0) as usize),
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(stream) = ctx.db.get_stream(&args[0])? else {
+        return Ok(Value::Array(vec![]));
+    };
+    if count == Some(0) {
+        return Ok(Value::NullArray);
+    }
+    let count = count.unwrap_or(usize::MAX);
+    let entries: Vec<Value> = if rev {
+        stream
+            .rev_range(start, end)
+            .take(count)
+            .map(entry_reply)
+            .collect()
+    } else {
+        stream
+            .range(start, end)
+            .take(count)
+            .map(entry_reply)
+            .collect()
+    };
+    Ok(Value::Array(entries))
+}
+
+fn xrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    range(ctx, args, false)
+}
+
+fn xrevrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    range(ctx, args, true)
+}
+
+fn xlen(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx
+        .db
+        .get_stream(&args[0])?
+        .map_or(0, |stream| stream.len());
+    Ok(Value::Integer(len as i64))
+}
+
+/// `XDEL key id [id ...]`
+fn xdel(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let ids = args[1..]
+        .iter()
+        .map(|arg| parse_id(arg, 0))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(stream) = ctx.db.get_stream(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let deleted = ids.into_iter().filter(|&id| stream.remove(id)).count();
+    Ok(Value::Integer(deleted as i64))
+}
+
+/// `XTRIM key MAXLEN | MINID [= | ~] threshold [LIMIT count]`
+fn xtrim(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let mut trim = TrimOptions::default();
+    let mut i = 1;
+    while i < args.len() {
+        match keyword(&args[i]).as_str() {
+            "maxlen" | "minid" | "limit" => i += trim.parse(&args[i..])?,
+            _ => return Err(RespError::Syntax),
+        }
+    }
+    let Some(trim) = trim.finish(ctx.config.stream_node_max())? else {
+        return Err(RespError::Syntax);
+    };
+    let removed = match ctx.db.get_stream(&args[0])? {
+        Some(stream) => stream.trim(trim),
+        None => 0,
+    };
+    Ok(Value::Integer(removed as i64))
+}
+
+fn now_millis() -> u64 {
+    unix_millis(SystemTime::now()) as u64
+}
+
+fn optional_integer(value: Option<u64>) -> Value {
+    value.map_or(Value::Null, |value| Value::Integer(value as i64))
+}
+
+/// The error for commands that need both the stream and its group.
+fn no_group(key: &[u8], group: &[u8]) -> RespError {
+    RespError::Prefixed(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+/// The stream at `key`, if it exists and has the consumer group `group`.
+fn stream_with_group<'a>(
+    db: &'a mut Storage,
+    key: &[u8],
+    group: &[u8],
+) -> Result<Option<&'a mut Stream>, RespError> {
+    Ok(db
+        .get_stream(key)?
+        .filter(|stream| stream.groups.contains_key(group)))
+}
+
+/// Replies to a stream read: a map from key to entries under RESP3,
+/// `[[key, entries], ...]` under RESP2, and null if nothing was read.
+fn read_reply(protocol: Protocol, streams: Vec<(Bytes, Vec<Value>)>) -> Value {
+    if streams.is_empty() {
+        return Value::NullArray;
+    }
+    if protocol == Protocol::Resp3 {
+        Value::Map(
+            streams
+                .into_iter()
+                .map(|(key, entries)| (bulk(key), Value::Array(entries)))
+                .collect(),
+        )
+    } else {
+        Value::Array(
+            streams
+                .into_iter()
+                .map(|(key, entries)| Value::Array(vec![bulk(key), Value::Array(entries)]))
+                .collect(),
+        )
+    }
+}
+
+/// `XGROUP CREATE key group id | $ [MKSTREAM] [ENTRIESREAD entries-read]`,
+/// `XGROUP SETID key group id | $ [ENTRIESREAD entries-read]`,
+/// `XGROUP DESTROY key group`, `XGROUP CREATECONSUMER key group consumer`
+/// or `XGROUP DELCONSUMER key group consumer`
+fn xgroup(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let subcommand = keyword(&args[0]);
+    let (key, group, rest) = match (subcommand.as_str(), &args[1..]) {
+        ("create" | "setid", [key, group, rest @ ..]) if !rest.is_empty() => (key, group, rest),
+        ("destroy", [key, group]) => (key, group, &[][..]),
+        ("createconsumer" | "delconsumer", [key, group, rest @ ..]) if rest.len() == 1 => {
+            (key, group, rest)
+        }
+        (other, _) => {
+            return Err(RespError::Other(format!(
+                "unknown subcommand or wrong number of arguments for '{}'. Try XGROUP HELP.",
+                other
+            )))
+        }
+    };
+
+    // For CREATE and SETID: the ID, `None` standing for `$`, and options.
+    let mut id = None;
+    let mut mkstream = false;
+    let mut entries_read = None;
+    if matches!(subcommand.as_str(), "create" | "setid") {
+        if rest[0].as_ref() != b"$" {
+            id = Some(parse_id(&rest[0], 0)?);
+        }
+        let mut i = 1;
+        while i < rest.len() {
+            match keyword(&rest[i]).as_str() {
+                "mkstream" if subcommand == "create" => {
+                    mkstream = true;
+                    i += 1;
+                }
+                "entriesread" if i + 1 < rest.len() => {
+                    let read = parse_i64(&rest[i + 1])?;
+                    if read < -1 {
+                        return Err(RespError::Other(
+                            "value for ENTRIESREAD must be positive or -1".to_owned(),
+                        ));
+                    }
+                    entries_read = u64::try_from(read).ok();
+                    i += 2;
+                }
+                _ => return Err(RespError::Syntax),
+            }
+        }
+    }
+
+    let stream = if mkstream {
+        Some(ctx.db.get_or_create_stream(key)?)
+    } else {
+        ctx.db.get_stream(key)?
+    };
+    let Some(stream) = stream else {
+        return Err(RespError::Other(
+            "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+                .to_owned(),
+        ));
+    };
+    let no_such_group = || {
+        RespError::Prefixed(format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        ))
+    };
+    let last_id = stream.last_id;
+    match subcommand.as_str() {
+        "create" => {
+            if stream.groups.contains_key(group) {
+                return Err(RespError::Prefixed(
+                    "BUSYGROUP Consumer Group name already exists".to_owned(),
+                ));
+            }
+            let group_state = ConsumerGroup::new(id.unwrap_or(last_id), entries_read);
+            stream.groups.insert(group.clone(), group_state);
+            Ok(ok())
+        }
+        "setid" => {
+            let group = stream.groups.get_mut(group).ok_or_else(no_such_group)?;
+            group.last_delivered = id.unwrap_or(last_id);
+            group.entries_read = entries_read;
+            Ok(ok())
+        }
+        "destroy" => {
+            let destroyed = stream.groups.remove(group).is_some();
+            if destroyed {
+                // Readers blocked on the group get their NOGROUP error.
+                ctx.db.blocking.signal(key);
+            }
+            Ok(Value::Integer(destroyed as i64))
+        }
+        "createconsumer" => {
+            let group = stream.groups.get_mut(group).ok_or_else(no_such_group)?;
+            let created = group.create_consumer(&rest[0], now_millis());
+            Ok(Value::Integer(created as i64))
+        }
+        _ => {
+            let group = stream.groups.get_mut(group).ok_or_else(no_such_group)?;
+            let pending = group.delete_consumer(&rest[0]).unwrap_or(0);
+            Ok(Value::Integer(pending as i64))
+        }
+    }
+}
+
+/// The options `XREAD` and `XREADGROUP` share, up to `STREAMS`.
+#[derive(Default)]
+struct ReadOptions {
+    /// 0 reads everything.
+    count: usize,
+    /// `BLOCK`'s timeout, `Some(None)` waiting forever.
+    block: Option<Option<Duration>>,
+    noack: bool,
+}
+
+impl ReadOptions {
+    /// Parses the options of `command`, returning them along with the keys
+    /// and IDs after `STREAMS`. Only `XREADGROUP` takes `NOACK`.
+    fn parse<'a>(
+        args: &'a [Bytes],
+        command: &str,
+    ) -> Result<(Self, &'a [Bytes], &'a [Bytes]), RespError> {
+        let mut options = Self::default();
+        let mut i = 0;
+        loop {
+            match args.get(i).map(|arg| keyword(arg)).as_deref() {
+                Some("count") if i + 1 < args.len() => {
+                    options.count = parse_i64(&args[i + 1])?.max(0) as usize;
+                    i += 2;
+                }
+                Some("block") if i + 1 < args.len() => {
+                    let millis = parse_i64(&args[i + 1]).map_err(|_| {
+                        RespError::Other("timeout is not an integer or out of range".to_owned())
+                    })?;
+                    if millis < 0 {
+                        return Err(RespError::Other("timeout is negative".to_owned()));
+                    }
+                    options.block =
+                        Some((millis > 0).then(|| Duration::from_millis(millis as u64)));
+                    i += 2;
+                }
+                Some("noack") if command == "xreadgroup" => {
+                    options.noack = true;
+                    i += 1;
+                }
+                Some("streams") => {
+                    i += 1;
+                    break;
+                }
+                _ => return Err(RespError::Syntax),
+            }
+        }
+        let streams = &args[i..];
+        if streams.is_empty() || streams.len() % 2 == 1 {
+            let id = if command == "xread" { "'$'" } else { "'>'" };
+            return Err(RespError::Other(format!(
+                "Unbalanced '{}' list of streams: for each stream key an ID or {} must be specified.",
+                command, id
+            )));
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        Ok((options, keys, ids))
+    }
+
+    fn count(&self) -> usize {
+        if self.count == 0 {
+            usize::MAX
+        } else {
+            self.count
+        }
+    }
+}
+
+/// Where `XREAD` reads a stream from.
+enum ReadId {
+    /// Entries after this ID; `$` is the last ID when the command ran.
+    After(StreamId),
+    /// `+`: the last entry.
+    Last,
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id
+/// [id ...]`
+fn xread(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (options, keys, ids) = ReadOptions::parse(args, "xread")?;
+    let mut resolved = Vec::with_capacity(ids.len());
+    for (key, id) in keys.iter().zip(ids) {
+        resolved.push(match id.as_ref() {
+            b"$" => {
+                let last_id = ctx.db.get_stream(key)?.map(|stream| stream.last_id);
+                ReadId::After(last_id.unwrap_or(StreamId::MIN))
+            }
+            b"+" => ReadId::Last,
+            arg => ReadId::After(parse_id(arg, 0)?),
+        });
+    }
+
+    let count = options.count();
+    let mut read = vec![];
+    for (key, id) in keys.iter().zip(&resolved) {
+        let Some(stream) = ctx.db.get_stream(key)? else {
+            continue;
+        };
+        let entries: Vec<Value> = match *id {
+            ReadId::Last => stream.last_entry().map(entry_reply).into_iter().collect(),
+            ReadId::After(after) => match after.next() {
+                Some(start) => stream
+                    .range(start, StreamId::MAX)
+                    .take(count)
+                    .map(entry_reply)
+                    .collect(),
+                None => vec![],
+            },
+        };
+        if !entries.is_empty() {
+            read.push((key.clone(), entries));
+        }
+    }
+    let Some(timeout) = options.block.filter(|_| read.is_empty()) else {
+        return Ok(read_reply(ctx.client.protocol, read));
+    };
+    // Once woken, `$` must still mean the last ID as of now rather than as
+    // of the write that woke us, so the retry spells it out.
+    let mut retry = args[..args.len() - ids.len()].to_vec();
+    retry.extend(ids.iter().zip(&resolved).map(|(arg, id)| match id {
+        ReadId::After(after) if arg.as_ref() == b"$" => Bytes::from(after.to_string()),
+        _ => arg.clone(),
+    }));
+    Ok(ctx.block_with_args(keys.to_vec(), timeout, Value::NullArray, retry))
+}
+
+/// Where `XREADGROUP` reads a stream from.
+enum GroupReadId {
+    /// `>`: entries never delivered to the group.
+    New,
+    /// The consumer's pending entries after this ID.
+    Pending(StreamId),
+}
+
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+/// [NOACK] STREAMS key [key ...] id [id ...]`
+fn xreadgroup(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    if keyword(&args[0]) != "group" {
+        return Err(RespError::Syntax);
+    }
+    let (group, consumer) = (&args[1], &args[2]);
+    let (options, keys, ids) = ReadOptions::parse(&args[3..], "xreadgroup")?;
+    let ids = ids
+        .iter()
+        .map(|arg| match arg.as_ref() {
+            b">" => Ok(GroupReadId::New),
+            b"$" => Err(RespError::Other(
+                "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+                    .to_owned(),
+            )),
+            arg => parse_id(arg, 0).map(GroupReadId::Pending),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for key in keys {
+        if stream_with_group(ctx.db, key, group)?.is_none() {
+            return Err(RespError::Prefixed(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(group)
+            )));
+        }
+    }
+
+    let count = options.count();
+    let now = now_millis();
+    let mut read = vec![];
+    for (key, id) in keys.iter().zip(ids) {
+        let stream = ctx.db.get_stream(key)?.expect("streams were checked above");
+        stream
+            .groups
+            .get_mut(group)
+            .expect("groups were checked above")
+            .consumer(consumer, now);
+        match id {
+            GroupReadId::New => {
+                let entries = stream.deliver_new(group, consumer, count, options.noack, now);
+                if !entries.is_empty() {
+                    read.push((key.clone(), entries.iter().map(entry_reply).collect()));
+                }
+            }
+            GroupReadId::Pending(after) => {
+                let entries = match after.next() {
+                    Some(start) => stream.deliver_pending(group, consumer, start, count, now),
+                    None => vec![],
+                };
+                let entries = entries
+                    .into_iter()
+                    .map(|(id, entry)| match entry {
+                        Some(entry) => entry_reply(&entry),
+                        None => Value::Array(vec![bulk(id.to_string()), Value::NullArray]),
+                    })
+                    .collect();
+                read.push((key.clone(), entries));
+            }
+        }
+    }
+    // Only reads of new entries come back empty, and those may wait.
+    match options.block {
+        Some(timeout) if read.is_empty() => Ok(ctx.block(keys.to_vec(), timeout, Value::NullArray)),
+        _ => Ok(read_reply(ctx.client.protocol, read)),
+    }
+}
+
+/// `XACK key group id [id ...]`
+fn xack(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let ids = args[2..]
+        .iter()
+        .map(|arg| parse_id(arg, 0))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(group) = ctx
+        .db
+        .get_stream(&args[0])?
+        .and_then(|stream| stream.groups.get_mut(&args[1]))
+    else {
+        return Ok(Value::Integer(0));
+    };
+    let acked = ids.into_iter().filter(|&id| group.ack(id)).count();
+    Ok(Value::Integer(acked as i64))
+}
+
+/// The extended form of `XPENDING`.
+struct PendingQuery {
+    min_idle: u64,
+    start: StreamId,
+    end: StreamId,
+    count: usize,
+    consumer: Option<Bytes>,
+}
+
+impl PendingQuery {
+    fn parse(min_idle: i64, args: &[Bytes]) -> Result<Self, RespError> {
+        let (start, end, count, consumer) = match args {
+            [start, end, count] => (start, end, count, None),
+            [start, end, count, consumer] => (start, end, count, Some(consumer.clone())),
+            _ => return Err(RespError::Syntax),
+        };
+        Ok(Self {
+            min_idle: min_idle.max(0) as u64,
+            start: parse_range_bound(start, true)?,
+            end: parse_range_bound(end, false)?,
+            count: parse_i64(count)?.max(0) as usize,
+            consumer,
+        })
+    }
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+fn xpending(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (key, group) = (&args[0], &args[1]);
+    let query = match &args[2..] {
+        [] => None,
+        [option, idle, rest @ ..] if keyword(option) == "idle" => {
+            Some(PendingQuery::parse(parse_i64(idle)?, rest)?)
+        }
+        rest => Some(PendingQuery::parse(0, rest)?),
+    };
+    let stream = stream_with_group(ctx.db, key, group)?.ok_or_else(|| no_group(key, group))?;
+    let group = &stream.groups[group.as_ref()];
+
+    let Some(query) = query else {
+        let (Some((first, _)), Some((last, _))) = (
+            group.pending.first_key_value(),
+            group.pending.last_key_value(),
+        ) else {
+            return Ok(Value::Array(vec![
+                Value::Integer(0),
+                Value::Null,
+                Value::Null,
+                Value::NullArray,
+            ]));
+        };
+        let consumers = group
+            .consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| {
+                Value::Array(vec![
+                    bulk(name.clone()),
+                    bulk(consumer.pending.len().to_string()),
+                ])
+            })
+            .collect();
+        return Ok(Value::Array(vec![
+            Value::Integer(group.pending.len() as i64),
+            bulk(first.to_string()),
+            bulk(last.to_string()),
+            Value::Array(consumers),
+        ]));
+    };
+
+    if query.start > query.end {
+        return Ok(Value::Array(vec![]));
+    }
+    let now = now_millis();
+    let entries = group
+        .pending
+        .range(query.start..=query.end)
+        .filter(|(_, pending)| {
+            query
+                .consumer
+                .as_ref()
+                .is_none_or(|consumer| pending.consumer == consumer)
+        })
+        .map(|(id, pending)| (id, pending, now.saturating_sub(pending.delivery_time)))
+        .filter(|&(_, _, idle)| idle >= query.min_idle)
+        .take(query.count)
+        .map(|(id, pending, idle)| {
+            Value::Array(vec![
+                bulk(id.to_string()),
+                bulk(pending.consumer.clone()),
+                Value::Integer(idle as i64),
+                Value::Integer(pending.delivery_count as i64),
+            ])
+        })
+        .collect();
+    Ok(Value::Array(entries))
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+/// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+/// [LASTID lastid]`
+fn xclaim(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let min_idle = parse_i64(&args[3])
+        .map_err(|_| RespError::Other("Invalid min-idle-time argument for XCLAIM".to_owned()))?
+        .max(0) as u64;
+    let mut ids = vec![];
+    let mut i = 4;
+    while let Some(id) = args.get(i).and_then(|arg| StreamId::parse(arg, 0)) {
+        ids.push(id);
+        i += 1;
+    }
+
+    let now = now_millis();
+    let mut delivery_time = now;
+    let mut retry_count = None;
+    let mut force = false;
+    let mut justid = false;
+    let mut last_id = None;
+    while i < args.len() {
+        let option = keyword(&args[i]);
+        let value = |name: &str| {
+            args.get(i + 1)
+                .and_then(|arg| parse_i64(arg).ok())
+                .ok_or_else(|| {
+                    RespError::Other(format!("Invalid {} option argument for XCLAIM", name))
+                })
+        };
+        match option.as_str() {
+            "force" => force = true,
+            "justid" => justid = true,
+            "idle" => delivery_time = now.saturating_sub(value("IDLE")?.max(0) as u64),
+            "time" => delivery_time = (value("TIME")?.max(0) as u64).min(now),
+            "retrycount" => retry_count = Some(value("RETRYCOUNT")?.max(0) as u64),
+            "lastid" if i + 1 < args.len() => last_id = Some(parse_id(&args[i + 1], 0)?),
+            _ => {
+                return Err(RespError::Other(format!(
+                    "Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&args[i])
+                )))
+            }
+        }
+        i += if matches!(option.as_str(), "force" | "justid") {
+            1
+        } else {
+            2
+        };
+    }
+
+    let stream = stream_with_group(ctx.db, key, group)?.ok_or_else(|| no_group(key, group))?;
+    let group_name = group;
+    let group = stream
+        .groups
+        .get_mut(group_name)
+        .expect("group was checked above");
+    if let Some(last_id) = last_id {
+        group.last_delivered = group.last_delivered.max(last_id);
+    }
+    group.consumer(consumer, now);
+    let mut claimed = vec![];
+    for id in ids {
+        let entry = stream.get(id).cloned();
+        let group = stream
+            .groups
+            .get_mut(group_name)
+            .expect("group was checked above");
+        let delivery_count = match (group.pending.get(&id), &entry) {
+            (Some(_), None) => {
+                // Deleted from the stream since it was delivered.
+                group.ack(id);
+                continue;
+            }
+            (Some(pending), Some(_)) => {
+                if now.saturating_sub(pending.delivery_time) < min_idle {
+                    continue;
+                }
+                pending.delivery_count
+            }
+            (None, Some(_)) if force => 1,
+            (None, _) => continue,
+        };
+        let delivery_count = match retry_count {
+            Some(count) => count,
+            None if justid => delivery_count,
+            None => delivery_count + 1,
+        };
+        group
+            .deliver(id, consumer, now, delivery_count)
+            .delivery_time = delivery_time;
+        claimed.push(match entry {
+            Some(entry) if !justid => entry_reply(&entry),
+            _ => bulk(id.to_string()),
+        });
+    }
+    Ok(Value::Array(claimed))
+}
+
+/// `XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+/// [JUSTID]`
+fn xautoclaim(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let min_idle = parse_i64(&args[3])
+        .map_err(|_| RespError::Other("Invalid min-idle-time argument for XAUTOCLAIM".to_owned()))?
+        .max(0) as u64;
+    let start = parse_range_bound(&args[4], true)?;
+    let mut count = 100;
+    let mut justid = false;
+    let mut i = 5;
+    while i < args.len() {
+        match keyword(&args[i]).as_str() {
+            "count" if i + 1 < args.len() => {
+                let requested = parse_i64(&args[i + 1])?;
+                if !(1..=i64::MAX / 10).contains(&requested) {
+                    return Err(RespError::Other("COUNT must be > 0".to_owned()));
+                }
+                count = requested as usize;
+                i += 2;
+            }
+            "justid" => {
+                justid = true;
+                i += 1;
+            }
+            _ => return Err(RespError::Syntax),
+        }
+    }
+
+    let stream = stream_with_group(ctx.db, key, group)?.ok_or_else(|| no_group(key, group))?;
+    let now = now_millis();
+    let group_name = group;
+    let group = stream
+        .groups
+        .get_mut(group_name)
+        .expect("group was checked above");
+    group.consumer(consumer, now);
+    // Each pending entry looked at costs an attempt, whether claimed or not.
+    let attempts = count * 10;
+    let candidates: Vec<(StreamId, u64, u64)> = group
+        .pending
+        .range(start..)
+        .take(attempts + 1)
+        .map(|(&id, pending)| (id, pending.delivery_time, pending.delivery_count))
+        .collect();
+    let mut claimed = vec![];
+    let mut deleted = vec![];
+    let mut visited = 0;
+    for &(id, delivered_at, delivery_count) in &candidates {
+        if visited == attempts || claimed.len() == count {
+            break;
+        }
+        visited += 1;
+        let entry = stream.get(id).cloned();
+        let group = stream
+            .groups
+            .get_mut(group_name)
+            .expect("group was checked above");
+        let Some(entry) = entry else {
+            group.ack(id);
+            deleted.push(bulk(id.to_string()));
+            continue;
+        };
+        if now.saturating_sub(delivered_at) < min_idle {
+            continue;
+        }
+        let delivery_count = if justid {
+            delivery_count
+        } else {
+            delivery_count + 1
+        };
+        group.deliver(id, consumer, now, delivery_count);
+        claimed.push(if justid {
+            bulk(id.to_string())
+        } else {
+            entry_reply(&entry)
+        });
+    }
+    let next = candidates
+        .get(visited)
+        .map_or(StreamId::MIN, |&(id, _, _)| id);
+    Ok(Value::Array(vec![
+        bulk(next.to_string()),
+        Value::Array(claimed),
+        Value::Array(deleted),
+    ]))
+}
+
+/// `XINFO STREAM key [FULL [COUNT count]]`, `XINFO GROUPS key` or
+/// `XINFO CONSUMERS key group`
+fn xinfo(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let subcommand = keyword(&args[0]);
+    let (key, rest) = match (subcommand.as_str(), &args[1..]) {
+        ("stream", [key, rest @ ..]) => (key, rest),
+        ("groups", [key]) => (key, &[][..]),
+        ("consumers", [key, group]) => (key, std::slice::from_ref(group)),
+        (other, _) => {
+            return Err(RespError::Other(format!(
+                "unknown subcommand or wrong number of arguments for '{}'. Try XINFO HELP.",
+                other
+            )))
+        }
+    };
+    // `FULL` lists up to this many entries, and as many pending entries per
+    // group and consumer; 0 lists all of them.
+    let full = match (subcommand.as_str(), rest) {
+        ("stream", []) => None,
+        ("stream", [option]) if keyword(option) == "full" => Some(10),
+        ("stream", [option, count_option, count])
+            if keyword(option) == "full" && keyword(count_option) == "count" =>
+        {
+            Some(parse_i64(count)?.max(0) as usize)
+        }
+        ("stream", _) => return Err(RespError::Syntax),
+        _ => None,
+    };
+    let Some(stream) = ctx.db.get_stream(key)? else {
+        return Err(RespError::Other("no such key".to_owned()));
+    };
+    let now = now_millis();
+    Ok(match subcommand.as_str() {
+        "stream" => match full {
+            None => stream_info(stream),
+            Some(count) => stream_info_full(stream, count),
+        },
+        "groups" => Value::Array(
+            stream
+                .groups
+                .iter()
+                .map(|(name, group)| {
+                    Value::Map(vec![
+                        (bulk("name"), bulk(name.clone())),
+                        (
+                            bulk("consumers"),
+                            Value::Integer(group.consumers.len() as i64),
+                        ),
+                        (bulk("pending"), Value::Integer(group.pending.len() as i64)),
+                        (
+                            bulk("last-delivered-id"),
+                            bulk(group.last_delivered.to_string()),
+                        ),
+                        (bulk("entries-read"), optional_integer(group.entries_read)),
+                        (bulk("lag"), optional_integer(stream.lag(group))),
+                    ])
+                })
+                .collect(),
+        ),
+        _ => {
+            let group = stream.groups.get(&rest[0]).ok_or_else(|| {
+                RespError::Prefixed(format!(
+                    "NOGROUP No such consumer group '{}' for key name '{}'",
+                    String::from_utf8_lossy(&rest[0]),
+                    String::from_utf8_lossy(key)
+                ))
+            })?;
+            Value::Array(
+                group
+                    .consumers
+                    .iter()
+                    .map(|(name, consumer)| {
+                        let inactive = consumer
+                            .active_time
+                            .map_or(-1, |active| now.saturating_sub(active) as i64);
+                        Value::Map(vec![
+                            (bulk("name"), bulk(name.clone())),
+                            (
+                                bulk("pending"),
+                                Value::Integer(consumer.pending.len() as i64),
+                            ),
+                            (
+                                bulk("idle"),
+                                Value::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                            ),
+                            (bulk("inactive"), Value::Integer(inactive)),
+                        ])
+                    })
+                    .collect(),
+            )
+        }
+    })
+}
+
+/// The fields `XINFO STREAM` starts with, with or without `FULL`.
+fn stream_info_header(stream: &Stream) -> Vec<(Value, Value)> {
+    let recorded_first = stream.first_entry().map_or(StreamId::MIN, |entry| entry.id);
+    vec![
+        (bulk("length"), Value::Integer(stream.len() as i64)),
+        (
+            bulk("radix-tree-keys"),
+            Value::Integer(stream.node_count() as i64),
+        ),
+        (
+            bulk("radix-tree-nodes"),
+            Value::Integer(stream.node_count() as i64 + 1),
+        ),
+        (bulk("last-generated-id"), bulk(stream.last_id.to_string())),
+        (
+            bulk("max-deleted-entry-id"),
+            bulk(stream.max_deleted_id.to_string()),
+        ),
+        (
+            bulk("entries-added"),
+            Value::Integer(stream.entries_added as i64),
+        ),
+        (
+            bulk("recorded-first-entry-id"),
+            bulk(recorded_first.to_string()),
+        ),
+    ]
+}
+
+fn stream_info(stream: &Stream) -> Value {
+    let mut fields = stream_info_header(stream);
+    fields.extend([
+        (bulk("groups"), Value::Integer(stream.groups.len() as i64)),
+        (
+            bulk("first-entry"),
+            stream.first_entry().map_or(Value::Null, entry_reply),
+        ),
+        (
+            bulk("last-entry"),
+            stream.last_entry().map_or(Value::Null, entry_reply),
+        ),
+    ]);
+    Value::Map(fields)
+}
+
+fn stream_info_full(stream: &Stream, count: usize) -> Value {
+    let count = if count == 0 { usize::MAX } else { count };
+    let groups = stream
+        .groups
+        .iter()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, pending)| {
+                    Value::Array(vec![
+                        bulk(id.to_string()),
+                        bulk(pending.consumer.clone()),
+                        Value::Integer(pending.delivery_time as i64),
+                        Value::Integer(pending.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(count)
+                        .filter_map(|id| Some((id, group.pending.get(id)?)))
+                        .map(|(id, pending)| {
+                            Value::Array(vec![
+                                bulk(id.to_string()),
+                                Value::Integer(pending.delivery_time as i64),
+                                Value::Integer(pending.delivery_count as i64),
+                            ])
+                        })
+                        .collect();
+                    let active_time = consumer.active_time.map_or(-1, |time| time as i64);
+                    Value::Map(vec![
+                        (bulk("name"), bulk(name.clone())),
+                        (bulk("seen-time"), Value::Integer(consumer.seen_time as i64)),
+                        (bulk("active-time"), Value::Integer(active_time)),
+                        (
+                            bulk("pel-count"),
+                            Value::Integer(consumer.pending.len() as i64),
+                        ),
+                        (bulk("pending"), Value::Array(pending)),
+                    ])
+                })
+                .collect();
+            Value::Map(vec![
+                (bulk("name"), bulk(name.clone())),
+                (
+                    bulk("last-delivered-id"),
+                    bulk(group.last_delivered.to_string()),
+                ),
+                (bulk("entries-read"), optional_integer(group.entries_read)),
+                (bulk("lag"), optional_integer(stream.lag(group))),
+                (
+                    bulk("pel-count"),
+                    Value::Integer(group.pending.len() as i64),
+                ),
+                (bulk("pending"), Value::Array(pending)),
+                (bulk("consumers"), Value::Array(consumers)),
+            ])
+        })
+        .collect();
+    let mut fields = stream_info_header(stream);
+    fields.extend([
+        (
+            bulk("entries"),
+            Value::Array(stream.iter().take(count).map(entry_reply).collect()),
+        ),
+        (bulk("groups"), Value::Array(groups)),
+    ]);
+    Value::Map(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewId;
+
+    #[test]
+    fn parses_xadd_ids() {
+        assert!(matches!(NewId::parse(b"*"), Ok(NewId::Auto)));
+        assert!(matches!(NewId::parse(b"5-*"), Ok(NewId::AutoSeq(5))));
+        assert!(matches!(NewId::parse(b"5-3"), Ok(NewId::Explicit(_))));
+        // Only a bare timestamp may come before `-*`.
+        for invalid in [&b"1-2-*"[..], b"-*", b"x-*", b"0-0"] {
+            assert!(NewId::parse(invalid).is_err());
+        }
+    }
+}
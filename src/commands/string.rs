@@ -0,0 +1,580 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{bulk, keyword, ok, CommandSpec, Context};
+use crate::resp::{
+    resp::{Value, MAX_BULK_LEN},
+    RespError,
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        since: "1.0.0",
+        handler: set,
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key.",
+        since: "1.0.0",
+        handler: get,
+    },
+    CommandSpec {
+        name: "getset",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the previous string value of a key after setting it to a new value.",
+        since: "1.0.0",
+        handler: getset,
+    },
+    CommandSpec {
+        name: "getdel",
+        arity: 2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key after deleting the key.",
+        since: "6.2.0",
+        handler: getdel,
+    },
+    CommandSpec {
+        name: "getex",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key after setting its expiration time.",
+        since: "6.2.0",
+        handler: getex,
+    },
+    CommandSpec {
+        name: "setnx",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Set the string value of a key only when the key doesn't exist.",
+        since: "1.0.0",
+        handler: setnx,
+    },
+    CommandSpec {
+        name: "setex",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        handler: setex,
+    },
+    CommandSpec {
+        name: "psetex",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+        since: "2.6.0",
+        handler: psetex,
+    },
+    CommandSpec {
+        name: "mset",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        group: "string",
+        summary: "Atomically creates or modifies the string values of one or more keys.",
+        since: "1.0.1",
+        handler: mset,
+    },
+    CommandSpec {
+        name: "msetnx",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        group: "string",
+        summary: "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+        since: "1.0.1",
+        handler: msetnx,
+    },
+    CommandSpec {
+        name: "mget",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        group: "string",
+        summary: "Atomically returns the string values of one or more keys.",
+        since: "1.0.0",
+        handler: mget,
+    },
+    CommandSpec {
+        name: "incr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        handler: incr,
+    },
+    CommandSpec {
+        name: "decr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        handler: decr,
+    },
+    CommandSpec {
+        name: "incrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        handler: incrby,
+    },
+    CommandSpec {
+        name: "decrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        handler: decrby,
+    },
+    CommandSpec {
+        name: "incrbyfloat",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "2.6.0",
+        handler: incrbyfloat,
+    },
+    CommandSpec {
+        name: "append",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        handler: append,
+    },
+    CommandSpec {
+        name: "strlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the length of a string value.",
+        since: "2.2.0",
+        handler: strlen,
+    },
+    CommandSpec {
+        name: "getrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns a substring of the string stored at a key.",
+        since: "2.4.0",
+        handler: getrange,
+    },
+    CommandSpec {
+        name: "setrange",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        since: "2.2.0",
+        handler: setrange,
+    },
+];
+
+/// Condition and expiry options accepted by `SET`.
+#[derive(Default)]
+struct SetOptions {
+    nx: bool,
+    xx: bool,
+    get: bool,
+    keep_ttl: bool,
+    expiry: Option<SystemTime>,
+}
+
+impl SetOptions {
+    /// Parses `[NX | XX] [GET] [EX s | PX ms | EXAT ts | PXAT ts | KEEPTTL]`
+    /// in any order, rejecting conflicting combinations.
+    fn parse(args: &[Bytes]) -> Result<Self, RespError> {
+        let mut options = SetOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let option = keyword(arg);
+            match option.as_str() {
+                "nx" if !options.xx => options.nx = true,
+                "xx" if !options.nx => options.xx = true,
+                "get" => options.get = true,
+                "keepttl" if options.expiry.is_none() => options.keep_ttl = true,
+                "ex" | "px" | "exat" | "pxat" if options.expiry.is_none() && !options.keep_ttl => {
+                    let amount = args.next().ok_or(RespError::Syntax)?;
+                    options.expiry = Some(parse_expiry(&option, amount, "set")?);
+                }
+                _ => return Err(RespError::Syntax),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Turns an `EX`/`PX`/`EXAT`/`PXAT` argument into an absolute deadline.
+pub(crate) fn parse_expiry(
+    unit: &str,
+    amount: &[u8],
+    command: &str,
+) -> Result<SystemTime, RespError> {
+    let amount = parse_i64(amount)?;
+    let invalid = || RespError::Other(format!("invalid expire time in '{}' command", command));
+    if amount <= 0 {
+        return Err(invalid());
+    }
+    let millis = match unit {
+        "ex" | "exat" => amount.checked_mul(1000).ok_or_else(invalid)?,
+        _ => amount,
+    } as u64;
+    let deadline = match unit {
+        "ex" | "px" => SystemTime::now().checked_add(Duration::from_millis(millis)),
+        _ => UNIX_EPOCH.checked_add(Duration::from_millis(millis)),
+    };
+    deadline.ok_or_else(invalid)
+}
+
+/// Parses an integer the way Redis' `string2ll` does: no sign other than a
+/// leading `-`, no leading zeros and no surrounding whitespace.
+pub(crate) fn parse_i64(arg: &[u8]) -> Result<i64, RespError> {
+    let digits = arg.strip_prefix(b"-").unwrap_or(arg);
+    let canonical = match digits {
+        [] => false,
+        [b'0'] => arg.len() == 1,
+        [first, ..] => first.is_ascii_digit() && *first != b'0',
+    };
+    if !canonical {
+        return Err(RespError::NotInteger);
+    }
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<i64>().ok())
+        .ok_or(RespError::NotInteger)
+}
+
+/// Parses a finite float argument; `inf` is accepted for arguments but NaN
+/// never is.
+pub(crate) fn parse_f64(arg: &[u8]) -> Result<f64, RespError> {
+    let text = std::str::from_utf8(arg).map_err(|_| RespError::NotFloat)?;
+    if text.is_empty()
+        || text.starts_with(char::is_whitespace)
+        || text.ends_with(char::is_whitespace)
+    {
+        return Err(RespError::NotFloat);
+    }
+    let value = match text.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" | "infinity" | "+infinity" => f64::INFINITY,
+        "-inf" | "-infinity" => f64::NEG_INFINITY,
+        _ => text.parse::<f64>().map_err(|_| RespError::NotFloat)?,
+    };
+    if value.is_nan() {
+        return Err(RespError::NotFloat);
+    }
+    Ok(value)
+}
+
+/// `SET key value [NX | XX] [GET] [EX s | PX ms | EXAT ts | PXAT ts | KEEPTTL]`
+fn set(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let key = &args[0];
+    let options = SetOptions::parse(&args[2..])?;
+
+    let old = if options.get {
+        ctx.db.get(key.clone())?
+    } else {
+        Value::Null
+    };
+    let existing = ctx.db.lookup(key);
+    if (options.nx && existing.is_some()) || (options.xx && existing.is_none()) {
+        return Ok(if options.get { old } else { Value::Null });
+    }
+
+    let ttl = if options.keep_ttl {
+        existing.and_then(|item| item.ttl)
+    } else {
+        options.expiry
+    };
+    let reply = ctx.db.set(key.clone(), args[1].clone(), ttl);
+    Ok(if options.get { old } else { reply })
+}
+
+fn get(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    ctx.db.get(args[0].clone())
+}
+
+fn getset(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let old = ctx.db.get(args[0].clone())?;
+    ctx.db.set(args[0].clone(), args[1].clone(), None);
+    Ok(old)
+}
+
+fn getdel(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let old = ctx.db.get(args[0].clone())?;
+    ctx.db.remove(&args[0]);
+    Ok(old)
+}
+
+/// `GETEX key [EX s | PX ms | EXAT ts | PXAT ts | PERSIST]`
+fn getex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let mut ttl_change: Option<Option<SystemTime>> = None;
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
+        let option = keyword(arg);
+        match option.as_str() {
+            "persist" if ttl_change.is_none() => ttl_change = Some(None),
+            "ex" | "px" | "exat" | "pxat" if ttl_change.is_none() => {
+                let amount = options.next().ok_or(RespError::Syntax)?;
+                ttl_change = Some(Some(parse_expiry(&option, amount, "getex")?));
+            }
+            _ => return Err(RespError::Syntax),
+        }
+    }
+
+    let value = ctx.db.get(args[0].clone())?;
+    if let (Some(ttl), Some(item)) = (ttl_change, ctx.db.lookup_mut(&args[0])) {
+        item.ttl = ttl;
+    }
+    Ok(value)
+}
+
+fn setnx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    if ctx.db.lookup(&args[0]).is_some() {
+        return Ok(Value::Integer(0));
+    }
+    ctx.db.set(args[0].clone(), args[1].clone(), None);
+    Ok(Value::Integer(1))
+}
+
+fn setex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let ttl = parse_expiry("ex", &args[1], "setex")?;
+    Ok(ctx.db.set(args[0].clone(), args[2].clone(), Some(ttl)))
+}
+
+fn psetex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let ttl = parse_expiry("px", &args[1], "psetex")?;
+    Ok(ctx.db.set(args[0].clone(), args[2].clone(), Some(ttl)))
+}
+
+fn mset(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    if args.len() % 2 == 1 {
+        return Err(RespError::WrongArity("mset".to_owned()));
+    }
+    for pair in args.chunks(2) {
+        ctx.db.set(pair[0].clone(), pair[1].clone(), None);
+    }
+    Ok(ok())
+}
+
+fn msetnx(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    if args.len() % 2 == 1 {
+        return Err(RespError::WrongArity("msetnx".to_owned()));
+    }
+    if args.chunks(2).any(|pair| ctx.db.lookup(&pair[0]).is_some()) {
+        return Ok(Value::Integer(0));
+    }
+    for pair in args.chunks(2) {
+        ctx.db.set(pair[0].clone(), pair[1].clone(), None);
+    }
+    Ok(Value::Integer(1))
+}
+
+fn mget(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    Ok(Value::Array(
+        args.iter()
+            .map(|key| match ctx.db.get_string(key) {
+                Ok(Some(value)) => bulk(value),
+                _ => Value::Null,
+            })
+            .collect(),
+    ))
+}
+
+/// Adds `delta` to the integer stored at `key`, keeping its TTL.
+fn incr_by(ctx: &mut Context, key: &Bytes, delta: i64) -> Result<Value, RespError> {
+    let current = match ctx.db.get_string(key)? {
+        Some(value) => parse_i64(&value)?,
+        None => 0,
+    };
+    let updated = current
+        .checked_add(delta)
+        .ok_or_else(|| RespError::Other("increment or decrement would overflow".to_owned()))?;
+    ctx.db.set_keep_ttl(key.clone(), updated.to_string().into());
+    Ok(Value::Integer(updated))
+}
+
+fn incr(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    incr_by(ctx, &args[0], 1)
+}
+
+fn decr(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    incr_by(ctx, &args[0], -1)
+}
+
+fn incrby(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    incr_by(ctx, &args[0], parse_i64(&args[1])?)
+}
+
+fn decrby(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let delta = parse_i64(&args[1])?
+        .checked_neg()
+        .ok_or_else(|| RespError::Other("decrement would overflow".to_owned()))?;
+    incr_by(ctx, &args[0], delta)
+}
+
+fn incrbyfloat(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let current = match ctx.db.get_string(&args[0])? {
+        Some(value) => parse_f64(&value)?,
+        None => 0.0,
+    };
+    let updated = current + parse_f64(&args[1])?;
+    if !updated.is_finite() {
+        return Err(RespError::Other(
+            "increment would produce NaN or Infinity".to_owned(),
+        ));
+    }
+    let updated = Bytes::from(format!("{}", updated));
+    ctx.db.set_keep_ttl(args[0].clone(), updated.clone());
+    Ok(bulk(updated))
+}
+
+/// The error for a write that would grow a string past `MAX_BULK_LEN`.
+fn too_long() -> RespError {
+    RespError::Other("string exceeds maximum allowed size (proto-max-bulk-len)".to_owned())
+}
+
+fn append(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let current_len = ctx.db.get_string(&args[0])?.map_or(0, |value| value.len());
+    if (current_len + args[1].len()) as i64 > MAX_BULK_LEN {
+        return Err(too_long());
+    }
+    let len = ctx.db.with_string_mut(&args[0], |value| {
+        value.extend_from_slice(&args[1]);
+        value.len()
+    })?;
+    Ok(Value::Integer(len as i64))
+}
+
+fn strlen(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx.db.get_string(&args[0])?.map_or(0, |value| value.len());
+    Ok(Value::Integer(len as i64))
+}
+
+fn getrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (start, end) = (parse_i64(&args[1])?, parse_i64(&args[2])?);
+    let value = ctx.db.get_string(&args[0])?.unwrap_or_default();
+    let len = value.len() as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return Ok(bulk(""));
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if start > end {
+        return Ok(bulk(""));
+    }
+    Ok(bulk(value.slice(start as usize..=end as usize)))
+}
+
+fn setrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let offset = parse_i64(&args[1])?;
+    if offset < 0 {
+        return Err(RespError::Other("offset is out of range".to_owned()));
+    }
+    let patch = &args[2];
+    let current_len = ctx.db.get_string(&args[0])?.map_or(0, |value| value.len());
+    if patch.is_empty() {
+        return Ok(Value::Integer(current_len as i64));
+    }
+    let (offset, end) = (offset as usize, offset as usize + patch.len());
+    if end as i64 > MAX_BULK_LEN {
+        return Err(too_long());
+    }
+
+    let len = ctx.db.with_string_mut(&args[0], |value| {
+        if value.len() < end {
+            value.resize(end, 0);
+        }
+        value[offset..end].copy_from_slice(patch);
+        value.len()
+    })?;
+    Ok(Value::Integer(len as i64))
+}
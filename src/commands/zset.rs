@@ -0,0 +1,1368 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{
+    bulk,
+    generic::{parse_cursor, parse_scan_options, ScanTarget},
+    keyword,
+    list::{parse_count, parse_multi_pop, resolve_range, MultiPop},
+    parse_random_count,
+    string::{parse_f64, parse_i64},
+    CommandSpec, Context, MAX_RANDOM_REPEATS,
+};
+use crate::{
+    blocking::parse_timeout,
+    resp::{
+        resp::{format_double, Protocol, Value},
+        RespError,
+    },
+    storage::{Item, RedisValue},
+    zset::{LexBound, LexRange, Range, ScoreRange, SortedSet},
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "zadd",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+        since: "1.2.0",
+        handler: zadd,
+    },
+    CommandSpec {
+        name: "zincrby",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Increments the score of a member in a sorted set.",
+        since: "1.2.0",
+        handler: zincrby,
+    },
+    CommandSpec {
+        name: "zrem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.",
+        since: "1.2.0",
+        handler: zrem,
+    },
+    CommandSpec {
+        name: "zcard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the number of members in a sorted set.",
+        since: "1.2.0",
+        handler: zcard,
+    },
+    CommandSpec {
+        name: "zscore",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the score of a member in a sorted set.",
+        since: "1.2.0",
+        handler: zscore,
+    },
+    CommandSpec {
+        name: "zmscore",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the score of one or more members in a sorted set.",
+        since: "6.2.0",
+        handler: zmscore,
+    },
+    CommandSpec {
+        name: "zrank",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the index of a member in a sorted set ordered by ascending scores.",
+        since: "2.0.0",
+        handler: zrank,
+    },
+    CommandSpec {
+        name: "zrevrank",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the index of a member in a sorted set ordered by descending scores.",
+        since: "2.0.0",
+        handler: zrevrank,
+    },
+    CommandSpec {
+        name: "zcount",
+        arity: 4,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the count of members in a sorted set that have scores within a range.",
+        since: "2.0.0",
+        handler: zcount,
+    },
+    CommandSpec {
+        name: "zlexcount",
+        arity: 4,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the number of members in a sorted set within a lexicographical range.",
+        since: "2.8.9",
+        handler: zlexcount,
+    },
+    CommandSpec {
+        name: "zrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a range of indexes.",
+        since: "1.2.0",
+        handler: zrange,
+    },
+    CommandSpec {
+        name: "zrangestore",
+        arity: -5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        group: "sorted_set",
+        summary: "Stores a range of members from sorted set in a key.",
+        since: "6.2.0",
+        handler: zrangestore,
+    },
+    CommandSpec {
+        name: "zrevrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a range of indexes in reverse order.",
+        since: "1.2.0",
+        handler: zrevrange,
+    },
+    CommandSpec {
+        name: "zrangebyscore",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a range of scores.",
+        since: "1.0.5",
+        handler: zrangebyscore,
+    },
+    CommandSpec {
+        name: "zrevrangebyscore",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a range of scores in reverse order.",
+        since: "2.2.0",
+        handler: zrevrangebyscore,
+    },
+    CommandSpec {
+        name: "zrangebylex",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a lexicographical range.",
+        since: "2.8.9",
+        handler: zrangebylex,
+    },
+    CommandSpec {
+        name: "zrevrangebylex",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns members in a sorted set within a lexicographical range in reverse order.",
+        since: "2.8.9",
+        handler: zrevrangebylex,
+    },
+    CommandSpec {
+        name: "zpopmin",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+        since: "5.0.0",
+        handler: zpopmin,
+    },
+    CommandSpec {
+        name: "zpopmax",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+        since: "5.0.0",
+        handler: zpopmax,
+    },
+    CommandSpec {
+        name: "zmpop",
+        arity: -4,
+        flags: &["write", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes the sorted set if the last member was popped.",
+        since: "7.0.0",
+        handler: zmpop,
+    },
+    CommandSpec {
+        name: "bzpopmin",
+        arity: -3,
+        flags: &["write", "blocking", "fast"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "5.0.0",
+        handler: bzpopmin,
+    },
+    CommandSpec {
+        name: "bzpopmax",
+        arity: -3,
+        flags: &["write", "blocking", "fast"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "5.0.0",
+        handler: bzpopmax,
+    },
+    CommandSpec {
+        name: "bzmpop",
+        arity: -5,
+        flags: &["write", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "7.0.0",
+        handler: bzmpop,
+    },
+    CommandSpec {
+        name: "zremrangebyrank",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes members in a sorted set within a range of indexes. Deletes the sorted set if all members were removed.",
+        since: "2.0.0",
+        handler: zremrangebyrank,
+    },
+    CommandSpec {
+        name: "zremrangebyscore",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes members in a sorted set within a range of scores. Deletes the sorted set if all members were removed.",
+        since: "1.2.0",
+        handler: zremrangebyscore,
+    },
+    CommandSpec {
+        name: "zremrangebylex",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Removes members in a sorted set within a lexicographical range. Deletes the sorted set if all members were removed.",
+        since: "2.8.9",
+        handler: zremrangebylex,
+    },
+    CommandSpec {
+        name: "zunion",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Returns the union of multiple sorted sets.",
+        since: "6.2.0",
+        handler: zunion,
+    },
+    CommandSpec {
+        name: "zunionstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Stores the union of multiple sorted sets in a key.",
+        since: "2.0.0",
+        handler: zunionstore,
+    },
+    CommandSpec {
+        name: "zinter",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Returns the intersect of multiple sorted sets.",
+        since: "6.2.0",
+        handler: zinter,
+    },
+    CommandSpec {
+        name: "zinterstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Stores the intersect of multiple sorted sets in a key.",
+        since: "2.0.0",
+        handler: zinterstore,
+    },
+    CommandSpec {
+        name: "zintercard",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Returns the number of members of the intersect of multiple sorted sets.",
+        since: "7.0.0",
+        handler: zintercard,
+    },
+    CommandSpec {
+        name: "zdiff",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sorted_set",
+        summary: "Returns the difference between multiple sorted sets.",
+        since: "6.2.0",
+        handler: zdiff,
+    },
+    CommandSpec {
+        name: "zdiffstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Stores the difference of multiple sorted sets in a key.",
+        since: "6.2.0",
+        handler: zdiffstore,
+    },
+    CommandSpec {
+        name: "zrandmember",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Returns one or more random members from a sorted set.",
+        since: "6.2.0",
+        handler: zrandmember,
+    },
+    CommandSpec {
+        name: "zscan",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "sorted_set",
+        summary: "Iterates over members and scores of a sorted set.",
+        since: "2.8.0",
+        handler: zscan,
+    },
+];
+
+/// Replies with `entries`, adding their scores if `with_scores`: as
+/// [member, score] pairs under RESP3, flattened under RESP2.
+fn entries_reply(protocol: Protocol, entries: Vec<(Bytes, f64)>, with_scores: bool) -> Value {
+    Value::Array(if !with_scores {
+        entries
+            .into_iter()
+            .map(|(member, _)| bulk(member))
+            .collect()
+    } else if protocol == Protocol::Resp3 {
+        entries
+            .into_iter()
+            .map(|(member, score)| Value::Array(vec![bulk(member), Value::Double(score)]))
+            .collect()
+    } else {
+        entries
+            .into_iter()
+            .flat_map(|(member, score)| [bulk(member), Value::Double(score)])
+            .collect()
+    })
+}
+
+fn nan_error() -> RespError {
+    RespError::Other("resulting score is not a number (NaN)".to_owned())
+}
+
+#[derive(Default)]
+struct AddFlags {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+}
+
+/// `ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]`
+fn zadd(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let key = &args[0];
+    let mut flags = AddFlags::default();
+    let mut i = 1;
+    while i < args.len() {
+        match keyword(&args[i]).as_str() {
+            "nx" => flags.nx = true,
+            "xx" => flags.xx = true,
+            "gt" => flags.gt = true,
+            "lt" => flags.lt = true,
+            "ch" => flags.ch = true,
+            "incr" => flags.incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let pairs = &args[i..];
+    if pairs.is_empty() || pairs.len() % 2 == 1 {
+        return Err(RespError::Syntax);
+    }
+    if flags.nx && flags.xx {
+        return Err(RespError::Other(
+            "XX and NX options at the same time are not compatible".to_owned(),
+        ));
+    }
+    if (flags.nx && (flags.gt || flags.lt)) || (flags.gt && flags.lt) {
+        return Err(RespError::Other(
+            "GT, LT, and/or NX options at the same time are not compatible".to_owned(),
+        ));
+    }
+    if flags.incr && pairs.len() > 2 {
+        return Err(RespError::Other(
+            "INCR option supports a single increment-element pair".to_owned(),
+        ));
+    }
+    let pairs = pairs
+        .chunks(2)
+        .map(|pair| Ok((parse_f64(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<_>, RespError>>()?;
+
+    let zset = if flags.xx {
+        match ctx.db.get_zset(key)? {
+            Some(zset) => zset,
+            None if flags.incr => return Ok(Value::Null),
+            None => return Ok(Value::Integer(0)),
+        }
+    } else {
+        ctx.db.get_or_create_zset(key)?
+    };
+    let (mut added, mut changed) = (0, 0);
+    let mut incr_reply = Value::Null;
+    for (score, member) in pairs {
+        let score = match zset.score(&member) {
+            Some(_) if flags.nx => continue,
+            None if flags.xx => continue,
+            None => {
+                added += 1;
+                score
+            }
+            Some(current) => {
+                let score = if flags.incr { current + score } else { score };
+                if score.is_nan() {
+                    return Err(nan_error());
+                }
+                if (flags.gt && score <= current) || (flags.lt && score >= current) {
+                    continue;
+                }
+                if score != current {
+                    changed += 1;
+                }
+                score
+            }
+        };
+        zset.insert(member, score);
+        incr_reply = Value::Double(score);
+    }
+    Ok(if flags.incr {
+        incr_reply
+    } else if flags.ch {
+        Value::Integer(added + changed)
+    } else {
+        Value::Integer(added)
+    })
+}
+
+/// `ZINCRBY key increment member`
+fn zincrby(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let increment = parse_f64(&args[1])?;
+    let zset = ctx.db.get_or_create_zset(&args[0])?;
+    let score = zset.score(&args[2]).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        return Err(nan_error());
+    }
+    zset.insert(args[2].clone(), score);
+    Ok(Value::Double(score))
+}
+
+fn zrem(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let Some(zset) = ctx.db.get_zset(&args[0])? else {
+        return Ok(Value::Integer(0));
+    };
+    let removed = args[1..]
+        .iter()
+        .filter(|member| zset.remove(member))
+        .count();
+    ctx.db.remove_if_empty(&args[0]);
+    Ok(Value::Integer(removed as i64))
+}
+
+fn zcard(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let len = ctx.db.get_zset(&args[0])?.map_or(0, |zset| zset.len());
+    Ok(Value::Integer(len as i64))
+}
+
+fn zscore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let score = ctx
+        .db
+        .get_zset(&args[0])?
+        .and_then(|zset| zset.score(&args[1]));
+    Ok(score.map_or(Value::Null, Value::Double))
+}
+
+fn zmscore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let zset = ctx.db.get_zset(&args[0])?;
+    Ok(Value::Array(
+        args[1..]
+            .iter()
+            .map(|member| {
+                let score = zset.as_ref().and_then(|zset| zset.score(member));
+                score.map_or(Value::Null, Value::Double)
+            })
+            .collect(),
+    ))
+}
+
+fn zrank(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    rank(ctx, args, false)
+}
+
+fn zrevrank(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    rank(ctx, args, true)
+}
+
+/// `ZRANK key member [WITHSCORE]`, counting from the top if `reverse`.
+fn rank(ctx: &mut Context, args: &[Bytes], reverse: bool) -> Result<Value, RespError> {
+    let with_score = match &args[2..] {
+        [] => false,
+        [option] if keyword(option) == "withscore" => true,
+        _ => return Err(RespError::Syntax),
+    };
+    let Some(zset) = ctx.db.get_zset(&args[0])? else {
+        return Ok(Value::Null);
+    };
+    let (Some(rank), Some(score)) = (zset.rank(&args[1], reverse), zset.score(&args[1])) else {
+        return Ok(Value::Null);
+    };
+    Ok(if with_score {
+        Value::Array(vec![Value::Integer(rank as i64), Value::Double(score)])
+    } else {
+        Value::Integer(rank as i64)
+    })
+}
+
+/// Parses one end of a score interval, where a leading `(` excludes it.
+fn parse_score_bound(arg: &[u8]) -> Result<(f64, bool), RespError> {
+    let (number, exclusive) = match arg.strip_prefix(b"(") {
+        Some(number) => (number, true),
+        None => (arg, false),
+    };
+    parse_f64(number)
+        .map(|value| (value, exclusive))
+        .map_err(|_| RespError::Other("min or max is not a float".to_owned()))
+}
+
+fn parse_score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, RespError> {
+    let (min, min_exclusive) = parse_score_bound(min)?;
+    let (max, max_exclusive) = parse_score_bound(max)?;
+    Ok(ScoreRange {
+        min,
+        max,
+        min_exclusive,
+        max_exclusive,
+    })
+}
+
+fn parse_lex_bound(arg: &[u8]) -> Result<LexBound, RespError> {
+    match arg {
+        b"-" => Ok(LexBound::NegativeInfinity),
+        b"+" => Ok(LexBound::PositiveInfinity),
+        [b'[', member @ ..] => Ok(LexBound::Inclusive(Bytes::copy_from_slice(member))),
+        [b'(', member @ ..] => Ok(LexBound::Exclusive(Bytes::copy_from_slice(member))),
+        _ => Err(RespError::Other(
+            "min or max not valid string range item".to_owned(),
+        )),
+    }
+}
+
+fn parse_lex_range(min: &[u8], max: &[u8]) -> Result<LexRange, RespError> {
+    Ok(LexRange {
+        min: parse_lex_bound(min)?,
+        max: parse_lex_bound(max)?,
+    })
+}
+
+/// `ZCOUNT key min max`
+fn zcount(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let range = Range::Score(parse_score_range(&args[1], &args[2])?);
+    let count = ctx
+        .db
+        .get_zset(&args[0])?
+        .map_or(0, |zset| zset.count(&range));
+    Ok(Value::Integer(count as i64))
+}
+
+/// `ZLEXCOUNT key min max`
+fn zlexcount(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let range = Range::Lex(parse_lex_range(&args[1], &args[2])?);
+    let count = ctx
+        .db
+        .get_zset(&args[0])?
+        .map_or(0, |zset| zset.count(&range));
+    Ok(Value::Integer(count as i64))
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum By {
+    #[default]
+    Rank,
+    Score,
+    Lex,
+}
+
+/// A range query as `ZRANGE` takes it; the legacy commands (`ZREVRANGE`,
+/// `ZRANGEBYSCORE`, ...) start from a preset one.
+#[derive(Clone, Copy, Default)]
+struct RangeQuery {
+    by: By,
+    reverse: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl RangeQuery {
+    /// Parses the options after `start stop`. `BYSCORE`, `BYLEX` and `REV`
+    /// are only accepted by the unified commands.
+    fn parse(mut self, args: &[Bytes], unified: bool) -> Result<Self, RespError> {
+        let mut i = 0;
+        while i < args.len() {
+            match keyword(&args[i]).as_str() {
+                "withscores" => self.with_scores = true,
+                "limit" if i + 2 < args.len() => {
+                    self.limit = Some((parse_i64(&args[i + 1])?, parse_i64(&args[i + 2])?));
+                    i += 2;
+                }
+                "byscore" if unified => self.by = By::Score,
+                "bylex" if unified => self.by = By::Lex,
+                "rev" if unified => self.reverse = true,
+                _ => return Err(RespError::Syntax),
+            }
+            i += 1;
+        }
+        if self.limit.is_some() && self.by == By::Rank {
+            return Err(RespError::Other(
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                    .to_owned(),
+            ));
+        }
+        if self.with_scores && self.by == By::Lex {
+            return Err(RespError::Other(
+                "syntax error, WITHSCORES not supported in combination with BYLEX".to_owned(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Runs the query against the sorted set at `key`. `start` and `stop`
+    /// are ranks, scores or lex bounds depending on the query; reversed
+    /// score and lex queries take the upper bound first.
+    fn select(
+        &self,
+        ctx: &mut Context,
+        key: &[u8],
+        start: &[u8],
+        stop: &[u8],
+    ) -> Result<Vec<(Bytes, f64)>, RespError> {
+        let (min, max) = if self.reverse {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+        let range = match self.by {
+            By::Rank => None,
+            By::Score => Some(Range::Score(parse_score_range(min, max)?)),
+            By::Lex => Some(Range::Lex(parse_lex_range(min, max)?)),
+        };
+        let ranks = match self.by {
+            By::Rank => Some((parse_i64(start)?, parse_i64(stop)?)),
+            _ => None,
+        };
+        let Some(zset) = ctx.db.get_zset(key)? else {
+            return Ok(vec![]);
+        };
+        let range = match (range, ranks) {
+            (Some(range), _) => range,
+            (None, Some((start, stop))) => {
+                let len = zset.len();
+                let Some((start, stop)) = resolve_range(start, stop, len) else {
+                    return Ok(vec![]);
+                };
+                // Ranks of a reversed query count from the top.
+                if self.reverse {
+                    Range::Rank(len - 1 - stop, len - 1 - start)
+                } else {
+                    Range::Rank(start, stop)
+                }
+            }
+            (None, None) => unreachable!("rank queries parse their ranks"),
+        };
+        let (offset, count) = match self.limit {
+            None => (0, None),
+            Some((offset, _)) if offset < 0 => return Ok(vec![]),
+            // A negative count means no limit.
+            Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+        };
+        Ok(zset.range(&range, self.reverse, offset, count))
+    }
+}
+
+/// Runs a range command of the form `key start stop [options]`.
+fn range_command(
+    ctx: &mut Context,
+    args: &[Bytes],
+    query: RangeQuery,
+    unified: bool,
+) -> Result<Value, RespError> {
+    let query = query.parse(&args[3..], unified)?;
+    let entries = query.select(ctx, &args[0], &args[1], &args[2])?;
+    Ok(entries_reply(
+        ctx.client.protocol,
+        entries,
+        query.with_scores,
+    ))
+}
+
+/// `ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]`
+fn zrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    range_command(ctx, args, RangeQuery::default(), true)
+}
+
+/// `ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count]`
+fn zrangestore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery::default().parse(&args[4..], true)?;
+    if query.with_scores {
+        return Err(RespError::Syntax);
+    }
+    let entries = query.select(ctx, &args[1], &args[2], &args[3])?;
+    let mut zset = SortedSet::new();
+    for (member, score) in entries {
+        zset.insert(member, score);
+    }
+    Ok(store(ctx, &args[0], zset))
+}
+
+fn zrevrange(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery {
+        reverse: true,
+        ..Default::default()
+    };
+    range_command(ctx, args, query, false)
+}
+
+fn zrangebyscore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery {
+        by: By::Score,
+        ..Default::default()
+    };
+    range_command(ctx, args, query, false)
+}
+
+fn zrevrangebyscore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery {
+        by: By::Score,
+        reverse: true,
+        ..Default::default()
+    };
+    range_command(ctx, args, query, false)
+}
+
+fn zrangebylex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery {
+        by: By::Lex,
+        ..Default::default()
+    };
+    range_command(ctx, args, query, false)
+}
+
+fn zrevrangebylex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let query = RangeQuery {
+        by: By::Lex,
+        reverse: true,
+        ..Default::default()
+    };
+    range_command(ctx, args, query, false)
+}
+
+fn zpopmin(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    pop(ctx, args, false)
+}
+
+fn zpopmax(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    pop(ctx, args, true)
+}
+
+/// Pops up to `count` entries from the sorted set at `key`, or `None` if
+/// there is no such key.
+fn pop_entries(
+    ctx: &mut Context,
+    key: &[u8],
+    count: usize,
+    max: bool,
+) -> Result<Option<Vec<(Bytes, f64)>>, RespError> {
+    let Some(zset) = ctx.db.get_zset(key)? else {
+        return Ok(None);
+    };
+    let popped = zset.pop(count, max);
+    ctx.db.remove_if_empty(key);
+    Ok(Some(popped))
+}
+
+/// `ZPOPMIN key [count]`, or `ZPOPMAX` if `max`.
+fn pop(ctx: &mut Context, args: &[Bytes], max: bool) -> Result<Value, RespError> {
+    let count = match &args[1..] {
+        [] => None,
+        [count] => Some(parse_count(count)?),
+        _ => return Err(RespError::Syntax),
+    };
+    let popped = pop_entries(ctx, &args[0], count.unwrap_or(1), max)?.unwrap_or_default();
+    Ok(match count {
+        // Without a count the pair comes back flat, whatever the protocol.
+        None => Value::Array(
+            popped
+                .into_iter()
+                .flat_map(|(member, score)| [bulk(member), Value::Double(score)])
+                .collect(),
+        ),
+        Some(_) => entries_reply(ctx.client.protocol, popped, true),
+    })
+}
+
+/// Parses the `MIN | MAX` argument of `ZMPOP`/`BZMPOP` into whether to pop
+/// the highest scores.
+fn parse_max(arg: &[u8]) -> Result<bool, RespError> {
+    match keyword(arg).as_str() {
+        "min" => Ok(false),
+        "max" => Ok(true),
+        _ => Err(RespError::Syntax),
+    }
+}
+
+/// Pops from the first non-empty sorted set among `keys`, replying with the
+/// key and the popped [member, score] pairs.
+fn multi_pop(ctx: &mut Context, request: &MultiPop<bool>) -> Result<Option<Value>, RespError> {
+    for key in &request.keys {
+        if let Some(popped) = pop_entries(ctx, key, request.count, request.end)? {
+            let popped = popped
+                .into_iter()
+                .map(|(member, score)| Value::Array(vec![bulk(member), Value::Double(score)]))
+                .collect();
+            return Ok(Some(Value::Array(vec![
+                bulk(key.clone()),
+                Value::Array(popped),
+            ])));
+        }
+    }
+    Ok(None)
+}
+
+/// `ZMPOP numkeys key [key ...] MIN | MAX [COUNT count]`
+fn zmpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let request = parse_multi_pop(args, parse_max)?;
+    Ok(multi_pop(ctx, &request)?.unwrap_or(Value::NullArray))
+}
+
+/// `BZPOPMIN`/`BZPOPMAX key [key ...] timeout`
+fn blocking_pop(ctx: &mut Context, args: &[Bytes], max: bool) -> Result<Value, RespError> {
+    let (timeout, keys) = args.split_last().expect("arity checked");
+    let timeout = parse_timeout(timeout)?;
+    for key in keys {
+        if let Some(popped) = pop_entries(ctx, key, 1, max)? {
+            let (member, score) = popped
+                .into_iter()
+                .next()
+                .expect("sorted sets are never empty");
+            return Ok(Value::Array(vec![
+                bulk(key.clone()),
+                bulk(member),
+                Value::Double(score),
+            ]));
+        }
+    }
+    Ok(ctx.block(keys.to_vec(), timeout, Value::NullArray))
+}
+
+fn bzpopmin(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    blocking_pop(ctx, args, false)
+}
+
+fn bzpopmax(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    blocking_pop(ctx, args, true)
+}
+
+/// `BZMPOP timeout numkeys key [key ...] MIN | MAX [COUNT count]`
+fn bzmpop(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let timeout = parse_timeout(&args[0])?;
+    let request = parse_multi_pop(&args[1..], parse_max)?;
+    match multi_pop(ctx, &request)? {
+        Some(reply) => Ok(reply),
+        None => Ok(ctx.block(request.keys, timeout, Value::NullArray)),
+    }
+}
+
+/// Removes the entries of the sorted set at `key` that `range` selects.
+fn remove_range(
+    ctx: &mut Context,
+    key: &[u8],
+    range: impl FnOnce(usize) -> Option<Range>,
+) -> Result<Value, RespError> {
+    let Some(zset) = ctx.db.get_zset(key)? else {
+        return Ok(Value::Integer(0));
+    };
+    let removed = range(zset.len()).map_or(0, |range| zset.remove_range(&range));
+    ctx.db.remove_if_empty(key);
+    Ok(Value::Integer(removed as i64))
+}
+
+/// `ZREMRANGEBYRANK key start stop`
+fn zremrangebyrank(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (start, stop) = (parse_i64(&args[1])?, parse_i64(&args[2])?);
+    remove_range(ctx, &args[0], |len| {
+        resolve_range(start, stop, len).map(|(start, stop)| Range::Rank(start, stop))
+    })
+}
+
+/// `ZREMRANGEBYSCORE key min max`
+fn zremrangebyscore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let range = Range::Score(parse_score_range(&args[1], &args[2])?);
+    remove_range(ctx, &args[0], |_| Some(range))
+}
+
+/// `ZREMRANGEBYLEX key min max`
+fn zremrangebylex(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let range = Range::Lex(parse_lex_range(&args[1], &args[2])?);
+    remove_range(ctx, &args[0], |_| Some(range))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, total: f64, score: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN; Redis settles for 0.
+            Aggregate::Sum => match total + score {
+                sum if sum.is_nan() => 0.0,
+                sum => sum,
+            },
+            Aggregate::Min => total.min(score),
+            Aggregate::Max => total.max(score),
+        }
+    }
+}
+
+/// The inputs and options of `ZUNION`, `ZINTER`, `ZDIFF` and their `STORE`
+/// variants.
+struct Combine {
+    keys: Vec<Bytes>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+/// Parses `numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM | MIN |
+/// MAX] [WITHSCORES]`. `ZDIFF` takes neither weights nor an aggregate, and
+/// the `STORE` variants take no `WITHSCORES`.
+fn parse_combine(
+    args: &[Bytes],
+    op: SetOp,
+    command: &str,
+    store: bool,
+) -> Result<Combine, RespError> {
+    let numkeys = parse_i64(&args[0])?;
+    if numkeys <= 0 {
+        return Err(RespError::Other(format!(
+            "at least 1 input key is needed for '{}' command",
+            command
+        )));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 1 {
+        return Err(RespError::Syntax);
+    }
+    let mut combine = Combine {
+        keys: args[1..=numkeys].to_vec(),
+        weights: vec![1.0; numkeys],
+        aggregate: Aggregate::Sum,
+        with_scores: false,
+    };
+    let mut rest = &args[numkeys + 1..];
+    while let Some(option) = rest.first() {
+        match keyword(option).as_str() {
+            "weights" if op != SetOp::Diff && rest.len() > numkeys => {
+                for (weight, arg) in combine.weights.iter_mut().zip(&rest[1..]) {
+                    *weight = parse_f64(arg)
+                        .map_err(|_| RespError::Other("weight value is not a float".to_owned()))?;
+                }
+                rest = &rest[numkeys + 1..];
+            }
+            "aggregate" if op != SetOp::Diff && rest.len() > 1 => {
+                combine.aggregate = match keyword(&rest[1]).as_str() {
+                    "sum" => Aggregate::Sum,
+                    "min" => Aggregate::Min,
+                    "max" => Aggregate::Max,
+                    _ => return Err(RespError::Syntax),
+                };
+                rest = &rest[2..];
+            }
+            "withscores" if !store => {
+                combine.with_scores = true;
+                rest = &rest[1..];
+            }
+            _ => return Err(RespError::Syntax),
+        }
+    }
+    Ok(combine)
+}
+
+/// The members and scores at `key` as an input of the combining commands,
+/// which also accept plain sets, scoring each member 1.
+fn input(ctx: &mut Context, key: &[u8]) -> Result<Option<HashMap<Bytes, f64>>, RespError> {
+    Ok(match ctx.db.lookup(key).map(|item| &item.value) {
+        None => None,
+        Some(RedisValue::ZSet(zset)) => Some(
+            zset.iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect(),
+        ),
+        Some(RedisValue::Set(set)) => Some(set.iter().map(|member| (member, 1.0)).collect()),
+        Some(_) => return Err(RespError::WrongType),
+    })
+}
+
+/// Combines the inputs of `request`; missing keys count as empty.
+fn combine(ctx: &mut Context, request: &Combine, op: SetOp) -> Result<SortedSet, RespError> {
+    let inputs = request
+        .keys
+        .iter()
+        .map(|key| input(ctx, key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let weighted = |score: f64, weight: f64| match score * weight {
+        // 0 * inf
+        product if product.is_nan() => 0.0,
+        product => product,
+    };
+    let mut result = HashMap::new();
+    match op {
+        SetOp::Union => {
+            for (input, &weight) in inputs.iter().zip(&request.weights) {
+                for (member, &score) in input.iter().flatten() {
+                    let score = weighted(score, weight);
+                    result
+                        .entry(member.clone())
+                        .and_modify(|total| *total = request.aggregate.apply(*total, score))
+                        .or_insert(score);
+                }
+            }
+        }
+        SetOp::Inter => {
+            if let Some(inputs) = inputs
+                .iter()
+                .map(Option::as_ref)
+                .collect::<Option<Vec<_>>>()
+            {
+                'members: for (member, &score) in inputs[0] {
+                    let mut total = weighted(score, request.weights[0]);
+                    for (other, &weight) in inputs[1..].iter().zip(&request.weights[1..]) {
+                        let Some(&score) = other.get(member) else {
+                            continue 'members;
+                        };
+                        total = request.aggregate.apply(total, weighted(score, weight));
+                    }
+                    result.insert(member.clone(), total);
+                }
+            }
+        }
+        SetOp::Diff => {
+            if let Some(first) = &inputs[0] {
+                for (member, &score) in first {
+                    if inputs[1..]
+                        .iter()
+                        .flatten()
+                        .all(|other| !other.contains_key(member))
+                    {
+                        result.insert(member.clone(), score);
+                    }
+                }
+            }
+        }
+    }
+    let mut zset = SortedSet::new();
+    for (member, score) in result {
+        zset.insert(member, score);
+    }
+    Ok(zset)
+}
+
+/// Replaces `destination` with `zset`, deleting it if empty, and replies
+/// with the resulting size.
+fn store(ctx: &mut Context, destination: &Bytes, zset: SortedSet) -> Value {
+    let len = zset.len();
+    ctx.db.remove(destination);
+    if len > 0 {
+        ctx.db
+            .insert(destination.clone(), Item::new(RedisValue::ZSet(zset)));
+    }
+    Value::Integer(len as i64)
+}
+
+fn combine_reply(
+    ctx: &mut Context,
+    args: &[Bytes],
+    op: SetOp,
+    command: &str,
+) -> Result<Value, RespError> {
+    let request = parse_combine(args, op, command, false)?;
+    let zset = combine(ctx, &request, op)?;
+    let entries = zset
+        .iter()
+        .map(|(member, score)| (member.clone(), score))
+        .collect();
+    Ok(entries_reply(
+        ctx.client.protocol,
+        entries,
+        request.with_scores,
+    ))
+}
+
+fn combine_store(
+    ctx: &mut Context,
+    args: &[Bytes],
+    op: SetOp,
+    command: &str,
+) -> Result<Value, RespError> {
+    let request = parse_combine(&args[1..], op, command, true)?;
+    let zset = combine(ctx, &request, op)?;
+    Ok(store(ctx, &args[0], zset))
+}
+
+fn zunion(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_reply(ctx, args, SetOp::Union, "zunion")
+}
+
+fn zunionstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_store(ctx, args, SetOp::Union, "zunionstore")
+}
+
+fn zinter(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_reply(ctx, args, SetOp::Inter, "zinter")
+}
+
+fn zinterstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_store(ctx, args, SetOp::Inter, "zinterstore")
+}
+
+fn zdiff(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_reply(ctx, args, SetOp::Diff, "zdiff")
+}
+
+fn zdiffstore(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    combine_store(ctx, args, SetOp::Diff, "zdiffstore")
+}
+
+/// `ZINTERCARD numkeys key [key ...] [LIMIT limit]`
+fn zintercard(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let numkeys = parse_i64(&args[0])?;
+    if numkeys <= 0 {
+        return Err(RespError::Other(
+            "numkeys should be greater than 0".to_owned(),
+        ));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 1 {
+        return Err(RespError::Other(
+            "Number of keys can't be greater than number of args".to_owned(),
+        ));
+    }
+    let limit = match &args[numkeys + 1..] {
+        [] => 0,
+        [option, limit] if keyword(option) == "limit" => {
+            let limit = parse_i64(limit)?;
+            if limit < 0 {
+                return Err(RespError::Other("LIMIT can't be negative".to_owned()));
+            }
+            limit as usize
+        }
+        _ => return Err(RespError::Syntax),
+    };
+    let request = Combine {
+        keys: args[1..=numkeys].to_vec(),
+        weights: vec![1.0; numkeys],
+        aggregate: Aggregate::Sum,
+        with_scores: false,
+    };
+    let len = combine(ctx, &request, SetOp::Inter)?.len();
+    // A limit of 0 means no limit.
+    let count = match limit {
+        0 => len,
+        limit => len.min(limit),
+    };
+    Ok(Value::Integer(count as i64))
+}
+
+/// `ZRANDMEMBER key [count [WITHSCORES]]`
+fn zrandmember(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let (count, with_scores) = match &args[1..] {
+        [] => (None, false),
+        [count] => (Some(parse_random_count(count)?), false),
+        [count, option] if keyword(option) == "withscores" => {
+            (Some(parse_random_count(count)?), true)
+        }
+        _ => return Err(RespError::Syntax),
+    };
+    let protocol = ctx.client.protocol;
+    let zset = ctx.db.get_zset(&args[0])?;
+    let Some(count) = count else {
+        return Ok(zset
+            .and_then(|zset| zset.random_entry())
+            .map_or(Value::Null, |(member, _)| bulk(member.clone())));
+    };
+    let Some(zset) = zset else {
+        return Ok(Value::Array(vec![]));
+    };
+    let entries = if count < 0 {
+        // Repetitions allowed: draw independently.
+        (0..count.unsigned_abs().min(MAX_RANDOM_REPEATS))
+            .filter_map(|_| {
+                zset.random_entry()
+                    .map(|(member, score)| (member.clone(), score))
+            })
+            .collect()
+    } else {
+        zset.random_distinct(count as usize)
+    };
+    Ok(entries_reply(protocol, entries, with_scores))
+}
+
+/// `ZSCAN key cursor [MATCH pattern] [COUNT count]`
+fn zscan(ctx: &mut Context, args: &[Bytes]) -> Result<Value, RespError> {
+    let cursor = parse_cursor(&args[1])?;
+    let options = parse_scan_options(&args[2..], ScanTarget::Members)?;
+    let mut entries = vec![];
+    let cursor = match ctx.db.get_zset(&args[0])? {
+        Some(zset) => zset.scan(cursor, options.count, |member, score| {
+            if options.matches(member) {
+                entries.push(bulk(member.clone()));
+                entries.push(bulk(format_double(score)));
+            }
+        }),
+        None => 0,
+    };
+    Ok(Value::Array(vec![
+        bulk(cursor.to_string()),
+        Value::Array(entries),
+    ]))
+}
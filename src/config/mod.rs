@@ -1,24 +1,102 @@
-use clap::{command, Arg};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::{command, value_parser, Arg};
+
+use crate::hash::ListpackLimits;
 
 #[derive(Debug)]
 pub struct Config {
     pub dir: Option<String>,
     pub dbfilename: Option<String>,
     pub port: Option<String>,
+    /// Tunables that `CONFIG SET` may change at runtime.
+    pub hash_max_listpack_entries: AtomicUsize,
+    pub hash_max_listpack_value: AtomicUsize,
+    pub set_max_intset_entries: AtomicUsize,
+    pub stream_node_max_entries: AtomicUsize,
 }
 
 impl Config {
     pub fn new() -> Self {
+        Self::parse_from(std::env::args_os())
+    }
+
+    /// Builds the configuration from command line arguments, the first
+    /// being the program name.
+    pub fn parse_from<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
         let args = command!()
             .arg(Arg::new("dir").short('d').long("dir"))
             .arg(Arg::new("dbfilename").short('f').long("dbfilename"))
             .arg(Arg::new("port").short('p').long("port"))
-            .get_matches();
+            .arg(
+                Arg::new("hash-max-listpack-entries")
+                    .long("hash-max-listpack-entries")
+                    .value_parser(value_parser!(usize))
+                    .default_value("128"),
+            )
+            .arg(
+                Arg::new("hash-max-listpack-value")
+                    .long("hash-max-listpack-value")
+                    .value_parser(value_parser!(usize))
+                    .default_value("64"),
+            )
+            .arg(
+                Arg::new("set-max-intset-entries")
+                    .long("set-max-intset-entries")
+                    .value_parser(value_parser!(usize))
+                    .default_value("512"),
+            )
+            .arg(
+                Arg::new("stream-node-max-entries")
+                    .long("stream-node-max-entries")
+                    .value_parser(value_parser!(usize))
+                    .default_value("100"),
+            )
+            .get_matches_from(args);
+        let tunable = |name: &str| AtomicUsize::new(*args.get_one::<usize>(name).unwrap());
 
         Self {
             dir: args.get_one::<String>("dir").map(|d| d.to_owned()),
             dbfilename: args.get_one::<String>("dbfilename").map(|d| d.to_owned()),
             port: args.get_one::<String>("port").map(|d| d.to_owned()),
+            hash_max_listpack_entries: tunable("hash-max-listpack-entries"),
+            hash_max_listpack_value: tunable("hash-max-listpack-value"),
+            set_max_intset_entries: tunable("set-max-intset-entries"),
+            stream_node_max_entries: tunable("stream-node-max-entries"),
+        }
+    }
+
+    /// The runtime tunable called `name` (or its pre-7.0 ziplist alias).
+    pub fn tunable(&self, name: &str) -> Option<&AtomicUsize> {
+        match name {
+            "hash-max-listpack-entries" | "hash-max-ziplist-entries" => {
+                Some(&self.hash_max_listpack_entries)
+            }
+            "hash-max-listpack-value" | "hash-max-ziplist-value" => {
+                Some(&self.hash_max_listpack_value)
+            }
+            "set-max-intset-entries" => Some(&self.set_max_intset_entries),
+            "stream-node-max-entries" => Some(&self.stream_node_max_entries),
+            _ => None,
+        }
+    }
+
+    pub fn set_max_intset(&self) -> usize {
+        self.set_max_intset_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn stream_node_max(&self) -> usize {
+        self.stream_node_max_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn hash_limits(&self) -> ListpackLimits {
+        ListpackLimits {
+            max_entries: self.hash_max_listpack_entries.load(Ordering::Relaxed),
+            max_value: self.hash_max_listpack_value.load(Ordering::Relaxed),
         }
     }
 
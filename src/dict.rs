@@ -0,0 +1,212 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    mem,
+};
+
+use crate::random::random_index;
+
+const MIN_BUCKETS: usize = 4;
+
+/// A chained hash table with a power-of-two number of buckets, modelled on
+/// Redis' `dict`.
+///
+/// Unlike `std::collections::HashMap` it exposes its bucket layout through
+/// [`Dict::scan`], whose reverse-binary cursor lets callers walk the table a
+/// few buckets at a time while it keeps changing underneath them, and through
+/// [`Dict::random_entry`] for sampling.
+pub struct Dict<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..MIN_BUCKETS).map(|_| Vec::new()).collect(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn mask(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    fn bucket_of<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.hasher.hash_one(key) as usize & self.mask()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.buckets[self.bucket_of(key)]
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let bucket = self.bucket_of(key);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Inserts `value` at `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(existing) = self.get_mut(&key) {
+            return Some(mem::replace(existing, value));
+        }
+        if self.len >= self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+        let bucket = self.bucket_of(&key);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let bucket = self.bucket_of(key);
+        let position = self.buckets[bucket]
+            .iter()
+            .position(|(k, _)| k.borrow() == key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(position);
+        self.len -= 1;
+        if self.buckets.len() > MIN_BUCKETS && self.len * 8 < self.buckets.len() {
+            self.resize((self.len.next_power_of_two()).max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    fn resize(&mut self, size: usize) {
+        let old = mem::replace(&mut self.buckets, (0..size).map(|_| Vec::new()).collect());
+        for (key, value) in old.into_iter().flatten() {
+            let bucket = self.bucket_of(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Visits the bucket addressed by `cursor` and returns the cursor of the
+    /// next one, or 0 once the whole table has been visited.
+    ///
+    /// The cursor is incremented with its bits reversed, as in Redis'
+    /// `dictScan`, so every entry present for the whole walk is reported at
+    /// least once even if the table grows or shrinks between calls (entries
+    /// may be reported more than once after a shrink).
+    pub fn scan(&self, cursor: u64, mut visit: impl FnMut(&K, &V)) -> u64 {
+        let mask = self.mask() as u64;
+        for (k, v) in &self.buckets[(cursor & mask) as usize] {
+            visit(k, v);
+        }
+        let mut cursor = cursor | !mask;
+        cursor = cursor.reverse_bits();
+        cursor = cursor.wrapping_add(1);
+        cursor.reverse_bits()
+    }
+
+    /// Scans buckets from `cursor` until at least `count` entries were
+    /// visited, or after `count * 10` buckets so that a sparse table does not
+    /// stall the caller, and returns the cursor to continue from.
+    pub fn scan_many(&self, mut cursor: u64, count: usize, mut visit: impl FnMut(&K, &V)) -> u64 {
+        let (mut visited, mut buckets) = (0, 0);
+        loop {
+            cursor = self.scan(cursor, |k, v| {
+                visited += 1;
+                visit(k, v);
+            });
+            buckets += 1;
+            if cursor == 0 || visited >= count || buckets >= count.saturating_mul(10) {
+                return cursor;
+            }
+        }
+    }
+
+    /// A random entry: a random non-empty bucket, then a random entry in it.
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        loop {
+            let bucket = &self.buckets[random_index(self.buckets.len())];
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[random_index(bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Clone for Dict<K, V> {
+    fn clone(&self) -> Self {
+        let mut dict = Dict::new();
+        for (k, v) in self.iter() {
+            dict.insert(k.clone(), v.clone());
+        }
+        dict
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dict;
+
+    #[test]
+    fn scans_everything_with_a_huge_count() {
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            dict.insert(i, ());
+        }
+        let mut seen = vec![];
+        let cursor = dict.scan_many(0, usize::MAX, |&k, _| seen.push(k));
+        assert_eq!(cursor, 0);
+        seen.sort();
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,150 @@
+/// Glob-style matching with the semantics of Redis' `stringmatchlen`, shared
+/// by `KEYS`, `SCAN ... MATCH` and anything else that takes a pattern.
+///
+/// Supports `*`, `?`, character classes such as `[abc]`, `[^a]` and `[a-z]`,
+/// and `\` to escape the next character, both inside and outside classes.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the most recent `*`: the pattern position after
+    // it and the next string position it may swallow up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    loop {
+        if p < pattern.len() && pattern[p] == b'*' {
+            // Consecutive stars behave like a single one.
+            while p < pattern.len() && pattern[p] == b'*' {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            backtrack = Some((p, s));
+            continue;
+        }
+
+        if s < string.len() && p < pattern.len() {
+            if let Some(next) = match_one(pattern, p, string[s], nocase) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        } else if s == string.len() && p == pattern.len() {
+            return true;
+        }
+
+        // Mismatch: let the last star swallow one more character.
+        match backtrack {
+            Some((star_p, star_s)) if star_s < string.len() => {
+                backtrack = Some((star_p, star_s + 1));
+                p = star_p;
+                s = star_s + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn fold(c: u8, nocase: bool) -> u8 {
+    if nocase {
+        c.to_ascii_lowercase()
+    } else {
+        c
+    }
+}
+
+/// Matches the single (non-star) token starting at `pattern[p]` against `c`,
+/// returning the position after the token on success.
+fn match_one(pattern: &[u8], mut p: usize, c: u8, nocase: bool) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'[' => {
+            p += 1;
+            let negate = pattern.get(p) == Some(&b'^');
+            if negate {
+                p += 1;
+            }
+            let mut matched = false;
+            loop {
+                match pattern.get(p) {
+                    // An unterminated class ends with the pattern.
+                    None => break,
+                    Some(b']') => {
+                        p += 1;
+                        break;
+                    }
+                    Some(b'\\') if p + 1 < pattern.len() => {
+                        matched |= fold(pattern[p + 1], nocase) == fold(c, nocase);
+                        p += 2;
+                    }
+                    Some(&start) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                        let end = pattern[p + 2];
+                        let (low, high) = if start <= end {
+                            (start, end)
+                        } else {
+                            (end, start)
+                        };
+                        let (low, high, c) =
+                            (fold(low, nocase), fold(high, nocase), fold(c, nocase));
+                        matched |= low <= c && c <= high;
+                        p += 3;
+                    }
+                    Some(&literal) => {
+                        matched |= fold(literal, nocase) == fold(c, nocase);
+                        p += 1;
+                    }
+                }
+            }
+            (matched != negate).then_some(p)
+        }
+        b'\\' if p + 1 < pattern.len() => {
+            (fold(pattern[p + 1], nocase) == fold(c, nocase)).then_some(p + 2)
+        }
+        literal => (fold(literal, nocase) == fold(c, nocase)).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("user:*:name", "user:42:name"));
+        assert!(!matches("user:*:name", "user:42:email"));
+        assert!(matches("*a*b*c", "xxaxxbxxbxxc"));
+        assert!(!matches("a*", "ba"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("a[bc", "ab"));
+    }
+
+    #[test]
+    fn escapes_and_case() {
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+        assert!(matches("a\\", "a\\"));
+        assert!(!matches("KEY", "key"));
+        assert!(glob_match(b"KEY*", b"key:1", true));
+    }
+}
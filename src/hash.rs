@@ -0,0 +1,268 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+
+use crate::{dict::Dict, random::random_index};
+
+/// Size limits under which a hash keeps the compact encoding, from
+/// `hash-max-listpack-entries` and `hash-max-listpack-value`.
+#[derive(Clone, Copy, Debug)]
+pub struct ListpackLimits {
+    pub max_entries: usize,
+    pub max_value: usize,
+}
+
+/// The value of a hash key.
+///
+/// Small hashes are kept as a flat vector of pairs, searched linearly, the
+/// way Redis keeps them in a listpack. Once a hash grows past the
+/// configured number of entries, or is given a field or value longer than
+/// the configured length, it is converted to a [`Dict`] for good.
+///
+/// Fields may carry their own TTL (`HEXPIRE`). Expired fields are dropped
+/// by [`Hash::purge_expired`], which the keyspace calls whenever the hash is
+/// looked up and from the active expiry cycle. The TTLs are also kept in
+/// deadline order, so finding nothing to purge costs a single comparison.
+#[derive(Clone, Debug)]
+pub struct Hash {
+    fields: Fields,
+    expires: HashMap<Bytes, SystemTime>,
+    deadlines: BTreeSet<(SystemTime, Bytes)>,
+}
+
+#[derive(Clone, Debug)]
+enum Fields {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Table(Dict<Bytes, Bytes>),
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self {
+            fields: Fields::Listpack(Vec::new()),
+            expires: HashMap::new(),
+            deadlines: BTreeSet::new(),
+        }
+    }
+
+    /// Builds a hash from `pairs`, picking the encoding they fit in.
+    pub fn from_pairs(pairs: Vec<(Bytes, Bytes)>, limits: ListpackLimits) -> Self {
+        let mut hash = Hash::new();
+        for (field, value) in pairs {
+            hash.insert(field, value, limits);
+        }
+        hash
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding(&self) -> &'static str {
+        match self.fields {
+            Fields::Listpack(_) if self.expires.is_empty() => "listpack",
+            Fields::Listpack(_) => "listpackex",
+            Fields::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.fields {
+            Fields::Listpack(pairs) => pairs.len(),
+            Fields::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match &self.fields {
+            Fields::Listpack(pairs) => pairs.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Fields::Table(table) => table.get(field),
+        }
+    }
+
+    /// Sets `field` to `value`, dropping any TTL the field had, and returns
+    /// whether the field is new.
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: ListpackLimits) -> bool {
+        self.clear_expire(&field);
+        if let Fields::Listpack(pairs) = &mut self.fields {
+            if let Some((_, existing)) = pairs.iter_mut().find(|(f, _)| *f == field) {
+                let fits = value.len() <= limits.max_value;
+                *existing = value;
+                if !fits {
+                    self.convert();
+                }
+                return false;
+            }
+            if pairs.len() < limits.max_entries
+                && field.len() <= limits.max_value
+                && value.len() <= limits.max_value
+            {
+                pairs.push((field, value));
+                return true;
+            }
+            self.convert();
+        }
+        match &mut self.fields {
+            Fields::Table(table) => table.insert(field, value).is_none(),
+            Fields::Listpack(_) => unreachable!("converted above"),
+        }
+    }
+
+    /// Removes `field`, returning whether it existed.
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        self.clear_expire(field);
+        match &mut self.fields {
+            Fields::Listpack(pairs) => match pairs.iter().position(|(f, _)| f == field) {
+                Some(position) => {
+                    pairs.remove(position);
+                    true
+                }
+                None => false,
+            },
+            Fields::Table(table) => table.remove(field).is_some(),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Fields::Listpack(pairs) = &mut self.fields {
+            let mut table = Dict::new();
+            for (field, value) in pairs.drain(..) {
+                table.insert(field, value);
+            }
+            self.fields = Fields::Table(table);
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + Send + '_> {
+        match &self.fields {
+            Fields::Listpack(pairs) => Box::new(pairs.iter().map(|(f, v)| (f, v))),
+            Fields::Table(table) => Box::new(table.iter()),
+        }
+    }
+
+    /// A random field and its value.
+    pub fn random_entry(&self) -> Option<(&Bytes, &Bytes)> {
+        match &self.fields {
+            Fields::Listpack(pairs) if pairs.is_empty() => None,
+            Fields::Listpack(pairs) => {
+                let (f, v) = &pairs[random_index(pairs.len())];
+                Some((f, v))
+            }
+            Fields::Table(table) => table.random_entry(),
+        }
+    }
+
+    /// One `HSCAN` step. Like Redis, a compact hash is returned whole in a
+    /// single call, with cursor 0.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&Bytes, &Bytes)) -> u64 {
+        match &self.fields {
+            Fields::Listpack(pairs) => {
+                for (field, value) in pairs {
+                    visit(field, value);
+                }
+                0
+            }
+            Fields::Table(table) => table.scan_many(cursor, count, visit),
+        }
+    }
+
+    /// When `field` expires, if it has a TTL.
+    pub fn expire_at(&self, field: &[u8]) -> Option<SystemTime> {
+        self.expires.get(field).copied()
+    }
+
+    /// Sets or clears the TTL of an existing field.
+    pub fn set_expire(&mut self, field: &Bytes, deadline: Option<SystemTime>) {
+        self.clear_expire(field);
+        if let Some(deadline) = deadline.filter(|_| self.get(field).is_some()) {
+            self.expires.insert(field.clone(), deadline);
+            self.deadlines.insert((deadline, field.clone()));
+        }
+    }
+
+    fn clear_expire(&mut self, field: &[u8]) {
+        if let Some((field, deadline)) = self.expires.remove_entry(field) {
+            self.deadlines.remove(&(deadline, field));
+        }
+    }
+
+    pub fn has_field_ttls(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Removes the fields whose TTL has passed by `now`, returning how many.
+    pub fn purge_expired(&mut self, now: SystemTime) -> usize {
+        let mut expired = 0;
+        while let Some((deadline, field)) = self.deadlines.first() {
+            if now < *deadline {
+                break;
+            }
+            let field = field.clone();
+            self.remove(&field);
+            expired += 1;
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Hash, ListpackLimits};
+
+    const LIMITS: ListpackLimits = ListpackLimits {
+        max_entries: 2,
+        max_value: 4,
+    };
+
+    #[test]
+    fn converts_past_the_entry_limit() {
+        let mut hash = Hash::new();
+        assert!(hash.insert("a".into(), "1".into(), LIMITS));
+        assert!(hash.insert("b".into(), "2".into(), LIMITS));
+        assert!(!hash.insert("a".into(), "3".into(), LIMITS));
+        assert_eq!(hash.encoding(), "listpack");
+        assert!(hash.insert("c".into(), "4".into(), LIMITS));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.len(), 3);
+        assert_eq!(hash.get(b"a".as_slice()).unwrap().as_ref(), b"3");
+    }
+
+    #[test]
+    fn converts_on_long_values() {
+        let mut hash = Hash::new();
+        hash.insert("a".into(), "1".into(), LIMITS);
+        assert!(!hash.insert("a".into(), "too long".into(), LIMITS));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert!(hash.remove(b"a".as_slice()));
+        assert!(hash.is_empty());
+    }
+
+    #[test]
+    fn field_ttls() {
+        let now = SystemTime::now();
+        let mut hash = Hash::new();
+        hash.insert("a".into(), "1".into(), LIMITS);
+        hash.insert("b".into(), "2".into(), LIMITS);
+        hash.set_expire(&"a".into(), Some(now - Duration::from_secs(1)));
+        hash.set_expire(&"b".into(), Some(now + Duration::from_secs(60)));
+        hash.set_expire(&"missing".into(), Some(now));
+        assert_eq!(hash.encoding(), "listpackex");
+        assert_eq!(hash.purge_expired(now), 1);
+        assert_eq!(hash.len(), 1);
+        assert_eq!(hash.purge_expired(now), 0);
+        // Moving a deadline forgets the old one.
+        hash.set_expire(&"b".into(), Some(now - Duration::from_secs(1)));
+        hash.set_expire(&"b".into(), Some(now + Duration::from_secs(1)));
+        assert_eq!(hash.purge_expired(now), 0);
+        assert_eq!(hash.expire_at(b"b"), Some(now + Duration::from_secs(1)));
+        // Overwriting a field drops its TTL.
+        hash.insert("b".into(), "3".into(), LIMITS);
+        assert!(!hash.has_field_ttls());
+    }
+}
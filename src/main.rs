@@ -1,9 +1,19 @@
 #![allow(unused_imports)]
 
+mod bitmap;
+mod blocking;
+mod commands;
 mod config;
+mod dict;
+mod glob;
+mod hash;
+mod random;
 mod resp;
 mod server;
+mod set;
 mod storage;
+mod stream;
+mod zset;
 
 use std::{fmt::Error, sync::Arc};
 
@@ -15,7 +25,12 @@ use tokio::net::TcpListener;
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let config = Arc::new(Config::new());
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", config.port.clone().unwrap_or("6379".to_owned()))).await.unwrap();
+    let listener = TcpListener::bind(format!(
+        "127.0.0.1:{}",
+        config.port.clone().unwrap_or("6379".to_owned())
+    ))
+    .await
+    .unwrap();
     let mut server = Server::new(listener);
     server.run(config).await;
     Ok(())
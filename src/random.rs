@@ -0,0 +1,16 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A random number good enough for sampling keys and members. Each
+/// `RandomState` is seeded differently, so no RNG state needs to be shared
+/// between connections.
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// A random index in `0..len`. `len` must not be zero.
+pub fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
@@ -1,19 +1,62 @@
 use std::fmt::Error;
 
+use resp::Value;
+
+#[allow(clippy::module_inception)]
 pub mod resp;
 #[derive(Debug)]
 pub enum RespError {
     InvalidBulkString(String),
     Other(String),
     Io(std::io::Error),
+    /// The client sent bytes that are not valid RESP; the connection is
+    /// closed after replying.
+    Protocol(String),
+    Syntax,
+    WrongType,
+    NotInteger,
+    NotFloat,
+    WrongArity(String),
+    // Reserved for `maxmemory` and `requirepass`, which are not configurable
+    // yet.
+    #[allow(dead_code)]
+    Oom,
+    #[allow(dead_code)]
+    NoAuth,
+    /// An error whose reply already starts with its own prefix, e.g.
+    /// `NOPROTO ...` or `WRONGPASS ...`.
+    Prefixed(String),
+}
+
+impl RespError {
+    /// The error reply sent to the client, including the Redis error prefix.
+    pub fn to_value(&self) -> Value {
+        Value::SimpleError(self.to_string())
+    }
 }
 
 impl std::fmt::Display for RespError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RespError::Other(msg) => msg.as_str().fmt(f),
-            RespError::InvalidBulkString(msg) => msg.as_str().fmt(f),
-            RespError::Io(msg) => (&msg.to_string()).as_str().fmt(f),
+            RespError::Other(msg) => write!(f, "ERR {}", msg),
+            RespError::InvalidBulkString(msg) | RespError::Protocol(msg) => {
+                write!(f, "ERR Protocol error: {}", msg)
+            }
+            RespError::Io(msg) => write!(f, "ERR {}", msg),
+            RespError::Syntax => f.write_str("ERR syntax error"),
+            RespError::WrongType => {
+                f.write_str("WRONGTYPE Operation against a key holding the wrong kind of value")
+            }
+            RespError::NotInteger => f.write_str("ERR value is not an integer or out of range"),
+            RespError::NotFloat => f.write_str("ERR value is not a valid float"),
+            RespError::WrongArity(command) => {
+                write!(f, "ERR wrong number of arguments for '{}' command", command)
+            }
+            RespError::Oom => {
+                f.write_str("OOM command not allowed when used memory > 'maxmemory'.")
+            }
+            RespError::NoAuth => f.write_str("NOAUTH Authentication required."),
+            RespError::Prefixed(msg) => f.write_str(msg),
         }
     }
 }
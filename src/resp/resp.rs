@@ -1,42 +1,142 @@
-use std::{
-    array,
-    fmt::{format, Error},
-};
-
 use super::RespError;
-use bytes::BytesMut;
+use bytes::{Buf, Bytes, BytesMut};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
 };
 
+/// Largest bulk string a client may send, mirroring Redis' `proto-max-bulk-len`.
+pub const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+/// Largest number of elements accepted in a multibulk request.
+pub const MAX_MULTIBULK_LEN: i64 = 1024 * 1024;
+/// Longest line accepted for an inline command.
+pub const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Wire protocol spoken on a connection, switched with `HELLO`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Value {
     SimpleString(String),
-    BulkString(String),
+    BulkString(Bytes),
     Array(Vec<Value>),
     SimpleError(String),
+    Integer(i64),
     Null,
+    NullArray,
+    // RESP3 types. Under RESP2 they are downgraded to their closest RESP2
+    // equivalent when serialized.
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    VerbatimString(String, Bytes),
+    Push(Vec<Value>),
+    Attribute(Vec<(Value, Value)>, Box<Value>),
 }
 
 impl Value {
-    pub fn serialize(self) -> String {
-        match &self {
-            Value::SimpleString(s) => format!("+{}\r\n", s),
-            Value::BulkString(b) => format!("${}\r\n{}\r\n", b.chars().count(), b),
-            Value::SimpleError(e) => format!("-${}\r\n", e),
-            Value::Null => format!("$-1\r\n"),
-            Value::Array(a) => {
-                let mut serialized = String::from(format!("*{}\r\n", a.len()));
-                for v in a {
-                    serialized.push_str(&v.clone().serialize());
+    pub fn serialize_with(self, protocol: Protocol) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.serialize_into(&mut out, protocol);
+        out
+    }
+
+    fn serialize_into(self, out: &mut Vec<u8>, protocol: Protocol) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            Value::SimpleString(s) => out.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Value::BulkString(b) => {
+                out.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
+                out.extend_from_slice(&b);
+                out.extend_from_slice(b"\r\n");
+            }
+            Value::SimpleError(e) => out.extend_from_slice(format!("-{}\r\n", e).as_bytes()),
+            Value::Integer(i) => out.extend_from_slice(format!(":{}\r\n", i).as_bytes()),
+            Value::Null | Value::NullArray if resp3 => out.extend_from_slice(b"_\r\n"),
+            Value::Null => out.extend_from_slice(b"$-1\r\n"),
+            Value::NullArray => out.extend_from_slice(b"*-1\r\n"),
+            Value::Array(a) => serialize_aggregate(out, b'*', a, protocol),
+            Value::Set(a) => serialize_aggregate(out, if resp3 { b'~' } else { b'*' }, a, protocol),
+            Value::Push(a) => {
+                serialize_aggregate(out, if resp3 { b'>' } else { b'*' }, a, protocol)
+            }
+            Value::Map(pairs) if resp3 => serialize_pairs(out, b'%', pairs, protocol),
+            Value::Map(pairs) => {
+                let flat = pairs.into_iter().flat_map(|(k, v)| [k, v]).collect();
+                serialize_aggregate(out, b'*', flat, protocol)
+            }
+            Value::Double(d) if resp3 => {
+                out.extend_from_slice(format!(",{}\r\n", format_double(d)).as_bytes())
+            }
+            Value::Double(d) => {
+                Value::BulkString(format_double(d).into()).serialize_into(out, protocol)
+            }
+            Value::Boolean(b) if resp3 => {
+                out.extend_from_slice(if b { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            Value::Boolean(b) => Value::Integer(b as i64).serialize_into(out, protocol),
+            Value::BigNumber(n) if resp3 => out.extend_from_slice(format!("({}\r\n", n).as_bytes()),
+            Value::BigNumber(n) => Value::BulkString(n.into()).serialize_into(out, protocol),
+            Value::VerbatimString(format, text) if resp3 => {
+                out.extend_from_slice(format!("={}\r\n{}:", text.len() + 4, format).as_bytes());
+                out.extend_from_slice(&text);
+                out.extend_from_slice(b"\r\n");
+            }
+            Value::VerbatimString(_, text) => Value::BulkString(text).serialize_into(out, protocol),
+            Value::Attribute(attributes, value) => {
+                if resp3 {
+                    serialize_pairs(out, b'|', attributes, protocol);
                 }
-                serialized
+                value.serialize_into(out, protocol);
             }
         }
     }
 }
 
+fn serialize_aggregate(out: &mut Vec<u8>, prefix: u8, items: Vec<Value>, protocol: Protocol) {
+    out.push(prefix);
+    out.extend_from_slice(format!("{}\r\n", items.len()).as_bytes());
+    for v in items {
+        v.serialize_into(out, protocol);
+    }
+}
+
+fn serialize_pairs(out: &mut Vec<u8>, prefix: u8, pairs: Vec<(Value, Value)>, protocol: Protocol) {
+    out.push(prefix);
+    out.extend_from_slice(format!("{}\r\n", pairs.len()).as_bytes());
+    for (k, v) in pairs {
+        k.serialize_into(out, protocol);
+        v.serialize_into(out, protocol);
+    }
+}
+
+/// Formats a double the way Redis replies with it: shortest round-trip
+/// representation, with `inf`, `-inf` and `nan` spelled out.
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_owned()
+    } else if d.is_infinite() {
+        if d > 0.0 { "inf" } else { "-inf" }.to_owned()
+    } else {
+        format!("{}", d)
+    }
+}
+
 pub struct RespHandler {
     stream: TcpStream,
     buffer: BytesMut,
@@ -49,43 +149,203 @@ impl RespHandler {
             buffer: BytesMut::with_capacity(512),
         }
     }
+
+    /// Returns the next complete frame from the connection.
+    ///
+    /// Bytes that belong to a frame which has not fully arrived yet stay in
+    /// `buffer`, so commands split across TCP segments are reassembled and
+    /// pipelined commands are handed out one per call, in order.
     pub async fn read_value(&mut self) -> Result<Option<Value>, RespError> {
-        let bytes_read = self
-            .stream
-            .read_buf(&mut self.buffer)
-            .await
-            .map_err(|e| RespError::Io(e))?;
+        loop {
+            // Drop blank lines as they arrive rather than rescanning them.
+            let blank = blank_lines_len(&self.buffer);
+            self.buffer.advance(blank);
+            if !self.buffer.is_empty() {
+                if let Some((v, len)) = parse_request(&self.buffer)? {
+                    self.buffer.advance(len);
+                    return Ok(Some(v));
+                }
+            }
 
-        if bytes_read == 0 {
-            return Ok(None);
+            let bytes_read = self
+                .stream
+                .read_buf(&mut self.buffer)
+                .await
+                .map_err(RespError::Io)?;
+
+            if bytes_read == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(RespError::Protocol(
+                    "Connection closed in the middle of a frame".to_owned(),
+                ));
+            }
         }
+    }
 
-        let (v, _) = parse_message(self.buffer.split())?;
-        Ok(Some(v))
-        // Ok(Some(Value::SimpleString(String::from("+OK\r\n"))))
+    /// Resolves once the peer closes the connection (or it fails).
+    ///
+    /// Whatever arrives meanwhile, such as commands pipelined after a
+    /// blocking one, is kept in `buffer` for [`RespHandler::read_value`].
+    pub async fn closed(&mut self) {
+        while let Ok(1..) = self.stream.read_buf(&mut self.buffer).await {}
     }
 
-    pub async fn write_value(&mut self, value: Value) -> Result<(), RespError> {
+    pub async fn write_value(&mut self, value: Value, protocol: Protocol) -> Result<(), RespError> {
         self.stream
-            .write(value.serialize().as_bytes())
+            .write_all(&value.serialize_with(protocol))
             .await
-            .map_err(|e| RespError::Io(e))?;
+            .map_err(RespError::Io)?;
         Ok(())
     }
 }
 
-pub fn parse_message(buffer: BytesMut) -> Result<(Value, usize), RespError> {
+/// Decodes one client request from the start of `buffer`.
+///
+/// Requests are either RESP arrays or, like in Redis, inline commands: a
+/// single line of space separated arguments as typed into `nc`/`telnet`.
+/// Inline commands come back as an array of bulk strings so they dispatch
+/// exactly like multibulk ones.
+pub fn parse_request(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+    let skipped = blank_lines_len(buffer);
+    let parsed = match buffer.get(skipped) {
+        None => return Ok(None),
+        Some(b'*') => RespParser::parse_multibulk(&buffer[skipped..])?,
+        Some(_) => RespParser::parse_inline(&buffer[skipped..])?,
+    };
+    Ok(parsed.map(|(value, len)| (value, skipped + len)))
+}
+
+/// Length of the complete blank lines at the start of `buffer`, which are
+/// skipped, as redis-server does for telnet users hitting enter.
+pub fn blank_lines_len(buffer: &[u8]) -> usize {
+    let mut len = 0;
+    for (i, &b) in buffer.iter().enumerate() {
+        match b {
+            b'\n' => len = i + 1,
+            b if b.is_ascii_whitespace() => {}
+            _ => break,
+        }
+    }
+    len
+}
+
+/// Decodes one frame from the start of `buffer`.
+///
+/// Returns `Ok(None)` when the buffer holds only part of a frame, otherwise
+/// the decoded value together with the number of bytes it occupied.
+pub fn parse_message(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
     match buffer[0] {
         b'+' => RespParser::parse_simple_string(buffer),
         b'$' => RespParser::parse_bulk_string(buffer),
         b'*' => RespParser::parse_array(buffer),
-        b'-' => RespParser::parse_simple_string(buffer),
-        _ => Err(RespError::Other(
-            format!("Unknown value type {:?}", buffer).to_owned(),
-        )),
+        b'-' => RespParser::parse_simple_error(buffer),
+        b':' => RespParser::parse_integer(buffer),
+        b'_' => RespParser::parse_null(buffer),
+        b',' => RespParser::parse_double(buffer),
+        b'#' => RespParser::parse_boolean(buffer),
+        b'(' => RespParser::parse_big_number(buffer),
+        b'=' => RespParser::parse_verbatim_string(buffer),
+        b'%' => RespParser::parse_map(buffer),
+        b'~' => RespParser::parse_set(buffer),
+        b'>' => RespParser::parse_push(buffer),
+        b'|' => RespParser::parse_attribute(buffer),
+        other => Err(RespError::Protocol(format!(
+            "unknown type byte '{}'",
+            other.escape_ascii()
+        ))),
     }
 }
 
+/// Splits an inline command line into arguments, honouring double quotes
+/// (with `\n`, `\r`, `\t`, `\b`, `\a`, `\\`, `\"` and `\xHH` escapes) and
+/// single quotes (with `\'`), following `sdssplitargs`.
+fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RespError> {
+    let unbalanced = || RespError::Protocol("unbalanced quotes in request".to_owned());
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            let Some(&c) = line.get(i) else {
+                if in_double || in_single {
+                    return Err(unbalanced());
+                }
+                break;
+            };
+            if in_double {
+                if c == b'\\' && i + 3 < line.len() && line[i + 1] == b'x' {
+                    let hex = std::str::from_utf8(&line[i + 2..i + 4]).ok();
+                    if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                        current.push(byte);
+                        i += 4;
+                        continue;
+                    }
+                }
+                if c == b'\\' && i + 1 < line.len() {
+                    i += 1;
+                    current.push(match line[i] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                } else if c == b'"' {
+                    // The closing quote must be followed by a space or nothing.
+                    if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                        return Err(unbalanced());
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                }
+            } else if in_single {
+                if c == b'\\' && line.get(i + 1) == Some(&b'\'') {
+                    i += 1;
+                    current.push(b'\'');
+                } else if c == b'\'' {
+                    if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                        return Err(unbalanced());
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                }
+            } else if c.is_ascii_whitespace() {
+                break;
+            } else if c == b'"' {
+                in_double = true;
+            } else if c == b'\'' {
+                in_single = true;
+            } else {
+                current.push(c);
+            }
+            i += 1;
+        }
+        args.push(current);
+    }
+}
+
+/// A decoded item and its size in bytes, or `None` if more input is needed.
+type Partial<T> = Result<Option<(T, usize)>, RespError>;
+
 fn read_until_crlf(buffer: &[u8]) -> Option<(&[u8], usize)> {
     for i in 1..buffer.len() {
         if buffer[i - 1] == b'\r' && buffer[i] == b'\n' {
@@ -97,66 +357,463 @@ fn read_until_crlf(buffer: &[u8]) -> Option<(&[u8], usize)> {
 }
 pub struct RespParser;
 impl RespParser {
-    fn parse_simple_string(buffer: BytesMut) -> Result<(Value, usize), RespError> {
-        if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-            let string = String::from_utf8(line.to_vec()).unwrap();
-            return Ok((Value::SimpleString(string), len + 1));
-        }
-        return Err(RespError::Other(format!("Invalid String {:?}", buffer)));
+    fn parse_simple_string(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let string = String::from_utf8(line.to_vec())
+            .map_err(|_| RespError::Protocol("Invalid UTF-8 sequence".to_owned()))?;
+        Ok(Some((Value::SimpleString(string), len + 1)))
+    }
+
+    fn parse_simple_error(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let string = String::from_utf8(line.to_vec())
+            .map_err(|_| RespError::Protocol("Invalid UTF-8 sequence".to_owned()))?;
+        Ok(Some((Value::SimpleError(string), len + 1)))
+    }
+
+    fn parse_integer(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        Ok(Some((Value::Integer(Self::parse_int(line)?), len + 1)))
     }
 
     fn parse_int(buffer: &[u8]) -> Result<i64, RespError> {
         let integer = String::from_utf8(buffer.to_vec())
-            .map_err(|_| RespError::Other("Invalid UTF-8 sequence".to_owned()))?;
+            .map_err(|_| RespError::Protocol("Invalid UTF-8 sequence".to_owned()))?;
 
         integer
             .parse::<i64>()
-            .map_err(|_| RespError::Other("Invalid Integer format".to_owned()))
+            .map_err(|_| RespError::Protocol("Invalid Integer format".to_owned()))
     }
 
-    fn parse_array(buffer: BytesMut) -> Result<(Value, usize), RespError> {
-        let (array_length, mut bytes_consumed) =
-            if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-                let array_length = Self::parse_int(line)?;
+    fn parse_array(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        if buffer.starts_with(b"*-1\r\n") {
+            return Ok(Some((Value::NullArray, 5)));
+        }
+        let Some((count, header)) = Self::parse_aggregate_len(buffer)? else {
+            return Ok(None);
+        };
+        Ok(
+            Self::parse_items(buffer, count, header)?
+                .map(|(items, len)| (Value::Array(items), len)),
+        )
+    }
 
-                (array_length, len + 1)
-            } else {
-                return Err(RespError::Other(
-                    format!("Invalid Array format {:?}", buffer).to_string(),
-                ));
+    /// Reads a request array, which like in Redis may only hold bulk
+    /// strings. Anything else is refused as soon as its type byte arrives,
+    /// so a request can never nest.
+    fn parse_multibulk(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        if buffer.starts_with(b"*-1\r\n") {
+            return Ok(Some((Value::NullArray, 5)));
+        }
+        let Some((count, mut consumed)) = Self::parse_aggregate_len(buffer)? else {
+            return Ok(None);
+        };
+        let mut items = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            match buffer.get(consumed) {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(other) => {
+                    return Err(RespError::Protocol(format!(
+                        "expected '$', got '{}'",
+                        other.escape_ascii()
+                    )))
+                }
+            }
+            let Some((item, length)) = parse_message(&buffer[consumed..])? else {
+                return Ok(None);
             };
-        let mut items = Vec::<Value>::new();
-        for _ in 0..array_length {
-            let (array_item, length) = parse_message(BytesMut::from(&buffer[bytes_consumed..]))?;
-            items.push(array_item);
-            bytes_consumed += length;
+            items.push(item);
+            consumed += length;
+        }
+        Ok(Some((Value::Array(items), consumed)))
+    }
+
+    fn parse_inline(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some(newline) = buffer.iter().position(|&b| b == b'\n') else {
+            if buffer.len() > MAX_INLINE_LEN {
+                return Err(RespError::Protocol("too big inline request".to_owned()));
+            }
+            return Ok(None);
+        };
+        if newline > MAX_INLINE_LEN {
+            return Err(RespError::Protocol("too big inline request".to_owned()));
+        }
+        let line = buffer[..newline]
+            .strip_suffix(b"\r")
+            .unwrap_or(&buffer[..newline]);
+        let consumed = newline + 1;
+
+        let args = split_inline_args(line)?;
+        if args.len() as i64 > MAX_MULTIBULK_LEN {
+            return Err(RespError::Protocol("invalid multibulk length".to_owned()));
+        }
+        let items = args
+            .into_iter()
+            .map(|arg| Value::BulkString(arg.into()))
+            .collect();
+        Ok(Some((Value::Array(items), consumed)))
+    }
+
+    fn parse_null(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        if !line.is_empty() {
+            return Err(RespError::Protocol("Invalid Null format".to_owned()));
         }
+        Ok(Some((Value::Null, len + 1)))
+    }
 
-        return Ok((Value::Array(items), bytes_consumed));
+    fn parse_double(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let double = match line {
+            b"inf" => f64::INFINITY,
+            b"-inf" => f64::NEG_INFINITY,
+            b"nan" => f64::NAN,
+            _ => std::str::from_utf8(line)
+                .ok()
+                .and_then(|d| d.parse::<f64>().ok())
+                .ok_or_else(|| RespError::Protocol("Invalid Double format".to_owned()))?,
+        };
+        Ok(Some((Value::Double(double), len + 1)))
     }
 
-    pub fn parse_bulk_string(buffer: BytesMut) -> Result<(Value, usize), RespError> {
-        let (bulk_string_length, bytes_consumed) =
-            if let Some((line, len)) = read_until_crlf(&buffer[1..]) {
-                let bulk_string_length = Self::parse_int(line)?;
+    fn parse_boolean(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let boolean = match line {
+            b"t" => true,
+            b"f" => false,
+            _ => return Err(RespError::Protocol("Invalid Boolean format".to_owned())),
+        };
+        Ok(Some((Value::Boolean(boolean), len + 1)))
+    }
 
-                (bulk_string_length, len + 1)
-            } else {
-                return Err(RespError::InvalidBulkString(format!(
-                    "Invalid Array format {:?}",
-                    buffer
-                )));
+    fn parse_big_number(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let digits = line.strip_prefix(b"-").unwrap_or(line);
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return Err(RespError::Protocol("Invalid Big Number format".to_owned()));
+        }
+        let number = String::from_utf8(line.to_vec())
+            .map_err(|_| RespError::Protocol("Invalid UTF-8 sequence".to_owned()))?;
+        Ok(Some((Value::BigNumber(number), len + 1)))
+    }
+
+    fn parse_verbatim_string(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((payload, total_parsed)) = Self::parse_blob(buffer)? else {
+            return Ok(None);
+        };
+        if payload.len() < 4 || payload[3] != b':' {
+            return Err(RespError::Protocol(
+                "Invalid Verbatim String format".to_owned(),
+            ));
+        }
+        let format = String::from_utf8(payload[..3].to_vec())
+            .map_err(|_| RespError::Protocol("Invalid UTF-8 sequence".to_owned()))?;
+        let text = Bytes::copy_from_slice(&payload[4..]);
+        Ok(Some((Value::VerbatimString(format, text), total_parsed)))
+    }
+
+    /// Reads the `<len>` header of an aggregate, returning the element count
+    /// and the size of the header.
+    fn parse_aggregate_len(buffer: &[u8]) -> Result<Option<(usize, usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let length = Self::parse_int(line)?;
+        if !(0..=MAX_MULTIBULK_LEN).contains(&length) {
+            return Err(RespError::Protocol("invalid multibulk length".to_owned()));
+        }
+        Ok(Some((length as usize, len + 1)))
+    }
+
+    fn parse_items(
+        buffer: &[u8],
+        count: usize,
+        mut bytes_consumed: usize,
+    ) -> Result<Option<(Vec<Value>, usize)>, RespError> {
+        let mut items = Vec::<Value>::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let Some((item, length)) = parse_message(&buffer[bytes_consumed..])? else {
+                return Ok(None);
             };
+            items.push(item);
+            bytes_consumed += length;
+        }
+        Ok(Some((items, bytes_consumed)))
+    }
+
+    fn parse_pairs(buffer: &[u8]) -> Partial<Vec<(Value, Value)>> {
+        let Some((count, header)) = Self::parse_aggregate_len(buffer)? else {
+            return Ok(None);
+        };
+        let Some((items, consumed)) = Self::parse_items(buffer, count * 2, header)? else {
+            return Ok(None);
+        };
+        let mut items = items.into_iter();
+        let mut pairs = Vec::with_capacity(count);
+        while let (Some(k), Some(v)) = (items.next(), items.next()) {
+            pairs.push((k, v));
+        }
+        Ok(Some((pairs, consumed)))
+    }
+
+    fn parse_map(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        Ok(Self::parse_pairs(buffer)?.map(|(pairs, len)| (Value::Map(pairs), len)))
+    }
+
+    fn parse_set(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((count, header)) = Self::parse_aggregate_len(buffer)? else {
+            return Ok(None);
+        };
+        Ok(Self::parse_items(buffer, count, header)?.map(|(items, len)| (Value::Set(items), len)))
+    }
+
+    fn parse_push(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((count, header)) = Self::parse_aggregate_len(buffer)? else {
+            return Ok(None);
+        };
+        Ok(Self::parse_items(buffer, count, header)?.map(|(items, len)| (Value::Push(items), len)))
+    }
+
+    /// Attributes annotate the value that follows them, so both are read
+    /// together.
+    fn parse_attribute(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        let Some((attributes, consumed)) = Self::parse_pairs(buffer)? else {
+            return Ok(None);
+        };
+        let Some((value, length)) = parse_message(&buffer[consumed..])? else {
+            return Ok(None);
+        };
+        Ok(Some((
+            Value::Attribute(attributes, Box::new(value)),
+            consumed + length,
+        )))
+    }
+
+    /// Reads a length-prefixed payload (`$` or `=`), returning the payload and
+    /// the total frame size.
+    fn parse_blob(buffer: &[u8]) -> Result<Option<(&[u8], usize)>, RespError> {
+        let Some((line, len)) = read_until_crlf(&buffer[1..]) else {
+            return Ok(None);
+        };
+        let blob_length = Self::parse_int(line)?;
+        if !(0..=MAX_BULK_LEN).contains(&blob_length) {
+            return Err(RespError::InvalidBulkString(
+                "invalid bulk length".to_owned(),
+            ));
+        }
 
-        let end_of_bulk_str = bytes_consumed + bulk_string_length as usize;
-        let total_parsed = end_of_bulk_str + 2;
-        return Ok((
-            Value::BulkString(
-                String::from_utf8(buffer[bytes_consumed..end_of_bulk_str].to_vec()).map_err(
-                    |e| RespError::Other(format!("Invalid UTF-8 format {:#?}", e.as_bytes())),
-                )?,
-            ),
+        let bytes_consumed = len + 1;
+        let end_of_blob = bytes_consumed + blob_length as usize;
+        let total_parsed = end_of_blob + 2;
+        if buffer.len() < total_parsed {
+            return Ok(None);
+        }
+        if &buffer[end_of_blob..total_parsed] != b"\r\n" {
+            return Err(RespError::InvalidBulkString(
+                "Bulk string is not terminated by CRLF".to_owned(),
+            ));
+        }
+        Ok(Some((&buffer[bytes_consumed..end_of_blob], total_parsed)))
+    }
+
+    pub fn parse_bulk_string(buffer: &[u8]) -> Result<Option<(Value, usize)>, RespError> {
+        if buffer.starts_with(b"$-1\r\n") {
+            return Ok(Some((Value::Null, 5)));
+        }
+        let Some((payload, total_parsed)) = Self::parse_blob(buffer)? else {
+            return Ok(None);
+        };
+        Ok(Some((
+            Value::BulkString(Bytes::copy_from_slice(payload)),
             total_parsed,
-        ));
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &[u8]) -> (Value, usize) {
+        parse_message(input).unwrap().unwrap()
+    }
+
+    fn round_trip(input: &[u8]) {
+        let (value, len) = parse_all(input);
+        assert_eq!(len, input.len());
+        assert_eq!(value.serialize_with(Protocol::Resp2), input);
+    }
+
+    #[test]
+    fn round_trips_every_resp2_type() {
+        round_trip(b"+OK\r\n");
+        round_trip(b"-ERR unknown command 'foobar'\r\n");
+        round_trip(b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n");
+        round_trip(b":0\r\n");
+        round_trip(b":1000\r\n");
+        round_trip(b":-42\r\n");
+        round_trip(b"$5\r\nhello\r\n");
+        round_trip(b"$0\r\n\r\n");
+        round_trip(b"$-1\r\n");
+        round_trip(b"*0\r\n");
+        round_trip(b"*-1\r\n");
+        round_trip(b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        round_trip(b"*3\r\n:1\r\n:2\r\n:3\r\n");
+        round_trip(b"*5\r\n:1\r\n:2\r\n:3\r\n:4\r\n$5\r\nhello\r\n");
+        round_trip(b"*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n*2\r\n+Hello\r\n-World\r\n");
+        round_trip(b"*3\r\n$5\r\nhello\r\n$-1\r\n$5\r\nworld\r\n");
+    }
+
+    fn round_trip_resp3(input: &[u8]) {
+        let (value, len) = parse_all(input);
+        assert_eq!(len, input.len());
+        assert_eq!(value.serialize_with(Protocol::Resp3), input);
+    }
+
+    #[test]
+    fn round_trips_every_resp3_type() {
+        round_trip_resp3(b"_\r\n");
+        round_trip_resp3(b",1.23\r\n");
+        round_trip_resp3(b",inf\r\n");
+        round_trip_resp3(b",-inf\r\n");
+        round_trip_resp3(b"#t\r\n");
+        round_trip_resp3(b"#f\r\n");
+        round_trip_resp3(b"(3492890328409238509324850943850943825024385\r\n");
+        round_trip_resp3(b"=15\r\ntxt:Some string\r\n");
+        round_trip_resp3(b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n");
+        round_trip_resp3(b"~2\r\n+orange\r\n+apple\r\n");
+        round_trip_resp3(b">2\r\n+message\r\n$5\r\nhello\r\n");
+        round_trip_resp3(b"|1\r\n+ttl\r\n:3600\r\n*2\r\n:1\r\n:2\r\n");
+    }
+
+    #[test]
+    fn resp3_types_downgrade_under_resp2() {
+        let downgrade = |input: &[u8]| parse_all(input).0.serialize_with(Protocol::Resp2);
+        assert_eq!(downgrade(b"_\r\n"), b"$-1\r\n");
+        assert_eq!(downgrade(b",1.5\r\n"), b"$3\r\n1.5\r\n");
+        assert_eq!(downgrade(b"#t\r\n"), b":1\r\n");
+        assert_eq!(downgrade(b"%1\r\n+a\r\n:1\r\n"), b"*2\r\n+a\r\n:1\r\n");
+        assert_eq!(downgrade(b"~1\r\n:1\r\n"), b"*1\r\n:1\r\n");
+        assert_eq!(downgrade(b"=7\r\ntxt:abc\r\n"), b"$3\r\nabc\r\n");
+        assert_eq!(downgrade(b"|1\r\n+a\r\n:1\r\n:7\r\n"), b":7\r\n");
+    }
+
+    #[test]
+    fn parses_nulls_and_errors_into_their_own_variants() {
+        assert!(matches!(parse_all(b"$-1\r\n").0, Value::Null));
+        assert!(matches!(parse_all(b"*-1\r\n").0, Value::NullArray));
+        assert!(matches!(parse_all(b":-7\r\n").0, Value::Integer(-7)));
+        match parse_all(b"-ERR boom\r\n").0 {
+            Value::SimpleError(e) => assert_eq!(e, "ERR boom"),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bulk_string_length_counts_bytes() {
+        let value = Value::BulkString(Bytes::from("€"));
+        assert_eq!(
+            value.serialize_with(Protocol::Resp2),
+            b"$3\r\n\xe2\x82\xac\r\n"
+        );
+    }
+
+    #[test]
+    fn incomplete_frames_need_more_data() {
+        for input in [
+            &b":12"[..],
+            b"$5\r\nhel",
+            b"$5\r\nhello\r",
+            b"*2\r\n$3\r\nfoo\r\n",
+            b"*2\r\n:1\r\n:2",
+        ] {
+            assert!(parse_message(input).unwrap().is_none(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn pipelined_frames_are_consumed_one_at_a_time() {
+        let input = b"*1\r\n$4\r\nPING\r\n:5\r\n";
+        let (_, first) = parse_all(input);
+        assert_eq!(first, 14);
+        assert!(matches!(parse_all(&input[first..]).0, Value::Integer(5)));
+    }
+
+    #[test]
+    fn parses_inline_commands() {
+        let (value, len) = parse_request(b"SET key \"hello world\"\r\nGET key\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(len, 23);
+        assert_eq!(
+            value.serialize_with(Protocol::Resp2),
+            b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$11\r\nhello world\r\n"
+        );
+
+        let (value, _) = parse_request(b"\r\n  ECHO 'it\\'s' \"\\x41\\n\"\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            value.serialize_with(Protocol::Resp2),
+            b"*3\r\n$4\r\nECHO\r\n$4\r\nit's\r\n$2\r\nA\n\r\n"
+        );
+
+        assert!(parse_request(b"PING").unwrap().is_none());
+        assert!(parse_request(b"ECHO \"oops\n").is_err());
+        assert!(parse_request(b"ECHO \"a\"b\n").is_err());
+        assert!(parse_request(&vec![b'a'; MAX_INLINE_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn skips_any_number_of_blank_lines() {
+        let mut buffer = b"\r\n".repeat(1_000_000);
+        assert!(parse_request(&buffer).unwrap().is_none());
+        assert_eq!(blank_lines_len(&buffer), buffer.len());
+
+        buffer.extend_from_slice(b"\n \t\n*1\r\n$4\r\nPING\r\n");
+        let (value, len) = parse_request(&buffer).unwrap().unwrap();
+        assert_eq!(len, buffer.len());
+        assert_eq!(
+            value.serialize_with(Protocol::Resp2),
+            b"*1\r\n$4\r\nPING\r\n"
+        );
+    }
+
+    #[test]
+    fn requests_only_hold_bulk_strings() {
+        let (value, len) = parse_request(b"*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(len, 22);
+        assert!(matches!(value, Value::Array(items) if items.len() == 2));
+        assert!(parse_request(b"*2\r\n$4\r\nECHO\r\n").unwrap().is_none());
+        assert!(parse_request(b"*1\r\n:1\r\n").is_err());
+        // Nesting is refused at the first inner header, however deep the
+        // client meant to go.
+        let nested = b"*1\r\n".repeat(1_000_000);
+        assert!(parse_request(&nested).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert!(parse_message(b":abc\r\n").is_err());
+        assert!(parse_message(b"$-2\r\n").is_err());
+        assert!(parse_message(b"$3\r\nfooXX").is_err());
+        assert!(parse_message(b"*-2\r\n").is_err());
     }
 }
@@ -1,24 +1,57 @@
 use std::{
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::{Duration, SystemTime},
 };
 
+use bytes::Bytes;
 use tokio::{
     fs::File,
     io::{AsyncReadExt, BufReader},
     net::{TcpListener, TcpStream},
-    sync::RwLock,
+    sync::{oneshot, RwLock},
     time,
 };
 
 use crate::{
+    blocking::BlockRequest,
+    commands,
     config::Config,
     resp::{
-        resp::{parse_message, RespHandler, RespParser, Value},
+        resp::{parse_message, Protocol, RespHandler, RespParser, Value},
         RespError,
     },
     storage::Storage,
 };
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How often the active expiry cycle runs.
+const EXPIRE_CYCLE_PERIOD: Duration = Duration::from_millis(100);
+
+/// State kept for each connection.
+#[derive(Clone)]
+pub struct Client {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Bytes>,
+    /// Set by a blocking command that found nothing to serve; the connection
+    /// is parked once the command returns.
+    pub block: Option<BlockRequest>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            block: None,
+        }
+    }
+}
+
 pub struct Server {
     listener: TcpListener,
 }
@@ -31,11 +64,26 @@ impl Server {
 
     pub async fn run(&mut self, config: Arc<Config>) {
         let db = Arc::new(RwLock::new(Storage::new()));
+        let db_expire = Arc::clone(&db);
+        tokio::spawn(async move {
+            // Like Redis with `hz 10`: ten cycles a second, each allowed a
+            // quarter of the period.
+            let mut interval_time = time::interval(EXPIRE_CYCLE_PERIOD);
+            loop {
+                interval_time.tick().await;
+                db_expire
+                    .write()
+                    .await
+                    .active_expire_cycle(EXPIRE_CYCLE_PERIOD / 4);
+            }
+        });
         if config.has_rdb() {
             let db_clone = Arc::clone(&db);
             let config_clone = Arc::clone(&config);
             tokio::spawn(async move {
-                let _ = db_clone.write().await.load_from_rdb(config_clone).await;
+                if let Err(e) = db_clone.write().await.load_from_rdb(config_clone).await {
+                    eprintln!("error loading RDB file: {}", e);
+                }
             });
             let config_clone = Arc::clone(&config);
             let db_save_storage = Arc::clone(&db);
@@ -83,103 +131,102 @@ impl Server {
         config: Arc<Config>,
     ) -> Result<(), RespError> {
         let mut handler = RespHandler::new(stream);
+        let mut client = Client::new();
         loop {
-            let value = handler.read_value().await.unwrap();
-            let response = if let Some(v) = value {
-                let (command, args) = Self::extract_command(v).unwrap();
-                match command.to_lowercase().as_str() {
-                    "ping" => Value::SimpleString("PONG".to_owned()),
-                    "echo" => args.first().unwrap().clone().to_owned(),
-                    "set" => {
-                        let key = unpack_bulk_string(args.get(0).unwrap().clone())?;
-                        let value = unpack_bulk_string(args.get(1).unwrap().clone())?;
-                        let mut ttl: Option<SystemTime> = None;
-                        if args.len() > 3 {
-                            let units =
-                                unpack_bulk_string(args.get(2).unwrap().clone())?.to_lowercase();
-                            let amount = match unpack_bulk_string(args.get(3).unwrap().clone()) {
-                                Ok(str) => str.parse::<u64>().map_err(|e| {
-                                    RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
-                                })?,
-                                Err(_) => {
-                                    return Err(RespError::Other(format!("unexpected expiry")))
-                                }
-                            };
-                            ttl = match units.as_str() {
-                                "px" => Some(SystemTime::now() + Duration::from_millis(amount)),
-                                "ex" => Some(SystemTime::now() + Duration::from_secs(amount)),
-                                _ => None,
-                            }
-                        };
-                        db.write().await.set(key, value, ttl).await
-                    }
-                    "get" => {
-                        let a = db
-                            .read()
-                            .await
-                            .get(unpack_bulk_string(args.first().unwrap().clone())?)
-                            .await;
-                        a
-                    }
-                    "config" => {
-                        if args.len() > 1 {
-                            let arg =
-                                unpack_bulk_string(args.first().unwrap().clone())?.to_lowercase();
-                            match arg.as_str() {
-                                "get" => {
-                                    let param = unpack_bulk_string(args.get(1).unwrap().clone())?;
-                                    let value = match param.as_str() {
-                                        "dir" => Value::BulkString(
-                                            config.dir.clone().unwrap_or_default(),
-                                        ),
-                                        "dbfilename" => Value::BulkString(
-                                            config.dbfilename.clone().unwrap_or_default(),
-                                        ),
-                                        _ => Value::SimpleError("Unknown arguments".to_owned()),
-                                    };
-
-                                    Value::Array(vec![Value::BulkString(param), value])
-                                }
-                                _ => Value::SimpleString("OK".to_owned()),
-                            }
-                        } else {
-                            Value::SimpleError("Invalid number of arguments".to_owned())
-                        }
-                    }
-                    "keys" => {
-                        let pattern = if args.len() > 0 {
-                            unpack_bulk_string(args.first().unwrap().clone())?.to_lowercase()
-                        } else {
-                            "*".to_owned()
-                        };
-                        db.read().await.keys(pattern)
+            let request = match handler.read_value().await {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(()),
+                Err(e) => return Self::close_with_error(&mut handler, &client, e).await,
+            };
+            let argv = match Self::extract_command(request) {
+                Ok(argv) => argv,
+                Err(e) => return Self::close_with_error(&mut handler, &client, e).await,
+            };
+
+            let (response, parked) = {
+                let mut db = db.write().await;
+                commands::run(&mut db, &config, &mut client, argv)
+            };
+            let response = match parked {
+                None => response,
+                Some((request, receiver)) => {
+                    match Self::wait_until_served(&mut handler, &db, client.id, request, receiver)
+                        .await
+                    {
+                        Some(response) => response,
+                        None => return Ok(()),
                     }
-                    _ => Value::SimpleError(format!("Cannot Handle command {}", command)),
                 }
-            } else {
-                return Ok(());
             };
-            println!("{:?}", response);
-            handler.write_value(response).await.unwrap();
-            ()
+            handler.write_value(response, client.protocol).await?;
+        }
+    }
+
+    /// Waits for a parked client to be served by another connection's write,
+    /// or for its timeout. Returns `None` if the client disconnects first.
+    ///
+    /// No lock is held while waiting.
+    async fn wait_until_served(
+        handler: &mut RespHandler,
+        db: &RwLock<Storage>,
+        id: u64,
+        request: BlockRequest,
+        mut receiver: oneshot::Receiver<Value>,
+    ) -> Option<Value> {
+        let expired = async {
+            match request.timeout {
+                Some(timeout) => time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let disconnected = tokio::select! {
+            reply = &mut receiver => return reply.ok(),
+            _ = expired => false,
+            _ = handler.closed() => true,
+        };
+
+        let mut db = db.write().await;
+        if db.blocking.unblock(id) {
+            return (!disconnected).then_some(request.timeout_reply);
+        }
+        // Served after all, between giving up and taking the lock.
+        let reply = receiver.try_recv().ok();
+        reply.filter(|_| !disconnected)
+    }
+
+    /// Reports a connection-level error to the client (unless the socket
+    /// itself failed) and ends the connection.
+    async fn close_with_error(
+        handler: &mut RespHandler,
+        client: &Client,
+        error: RespError,
+    ) -> Result<(), RespError> {
+        if !matches!(error, RespError::Io(_)) {
+            handler
+                .write_value(error.to_value(), client.protocol)
+                .await?;
         }
+        Err(error)
     }
 
-    fn extract_command(value: Value) -> Result<(String, Vec<Value>), RespError> {
+    fn extract_command(value: Value) -> Result<Vec<Bytes>, RespError> {
         match value {
-            Value::Array(a) => Ok((
-                unpack_bulk_string(a.first().unwrap().clone())?,
-                a.into_iter().skip(1).collect(),
-            )),
-            _ => Err(RespError::Other(format!("Unexpected Command format"))),
+            Value::Array(a) => a
+                .into_iter()
+                .map(|arg| {
+                    unpack_bulk_string(arg)
+                        .map_err(|_| RespError::Protocol("expected '$' in request".to_string()))
+                })
+                .collect(),
+            _ => Err(RespError::Protocol("expected '*' in request".to_string())),
         }
     }
 }
-pub fn unpack_bulk_string(value: Value) -> Result<String, RespError> {
+pub fn unpack_bulk_string(value: Value) -> Result<Bytes, RespError> {
     match value {
         Value::BulkString(s) => Ok(s),
-        _ => Err(RespError::Other(format!(
-            "Expected Command to be a Bulk String"
-        ))),
+        _ => Err(RespError::Other(
+            "Expected Command to be a Bulk String".to_string(),
+        )),
     }
 }
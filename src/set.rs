@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::{commands::string::parse_i64, dict::Dict, random::random_index};
+
+/// The value of a set key.
+///
+/// Sets made only of integers are kept as a sorted vector of `i64`, like
+/// Redis' intset, as long as they hold at most `set-max-intset-entries`
+/// members. Adding anything else converts them to a [`Dict`] for good.
+#[derive(Clone, Debug)]
+pub enum Set {
+    Intset(Vec<i64>),
+    Table(Dict<Bytes, ()>),
+}
+
+/// The integer `member` is stored as in an intset, if it is one.
+fn as_integer(member: &[u8]) -> Option<i64> {
+    if member.len() > 20 {
+        return None;
+    }
+    parse_i64(member).ok()
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Set::Intset(Vec::new())
+    }
+
+    /// Builds a set of `members`, picking the encoding they fit in.
+    pub fn from_members(members: impl IntoIterator<Item = Bytes>, max_intset: usize) -> Self {
+        let mut set = Set::new();
+        for member in members {
+            set.insert(member, max_intset);
+        }
+        set
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::Intset(_) => "intset",
+            Set::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::Intset(integers) => integers.len(),
+            Set::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::Intset(integers) => {
+                as_integer(member).is_some_and(|value| integers.binary_search(&value).is_ok())
+            }
+            Set::Table(table) => table.get(member).is_some(),
+        }
+    }
+
+    /// Adds `member`, returning whether it is new.
+    pub fn insert(&mut self, member: Bytes, max_intset: usize) -> bool {
+        if let Set::Intset(integers) = self {
+            match as_integer(&member) {
+                Some(value) => match integers.binary_search(&value) {
+                    Ok(_) => return false,
+                    Err(position) if integers.len() < max_intset => {
+                        integers.insert(position, value);
+                        return true;
+                    }
+                    Err(_) => self.convert(),
+                },
+                None => self.convert(),
+            }
+        }
+        match self {
+            Set::Table(table) => table.insert(member, ()).is_none(),
+            Set::Intset(_) => unreachable!("converted above"),
+        }
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::Intset(integers) => {
+                match as_integer(member).map(|value| integers.binary_search(&value)) {
+                    Some(Ok(position)) => {
+                        integers.remove(position);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Set::Table(table) => table.remove(member).is_some(),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Set::Intset(integers) = self {
+            let mut table = Dict::new();
+            for value in integers.drain(..) {
+                table.insert(Bytes::from(value.to_string()), ());
+            }
+            *self = Set::Table(table);
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + Send + '_> {
+        match self {
+            Set::Intset(integers) => {
+                Box::new(integers.iter().map(|value| Bytes::from(value.to_string())))
+            }
+            Set::Table(table) => Box::new(table.iter().map(|(member, _)| member.clone())),
+        }
+    }
+
+    pub fn random_member(&self) -> Option<Bytes> {
+        match self {
+            Set::Intset(integers) if integers.is_empty() => None,
+            Set::Intset(integers) => {
+                Some(integers[random_index(integers.len())].to_string().into())
+            }
+            Set::Table(table) => table.random_entry().map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// Up to `count` distinct random members.
+    pub fn random_distinct(&self, count: usize) -> Vec<Bytes> {
+        // Few members compared to the set: draw until enough distinct ones
+        // turn up, like Redis does, rather than copying the whole set.
+        if count.saturating_mul(3) < self.len() {
+            let mut picked = HashSet::with_capacity(count);
+            while picked.len() < count {
+                picked.extend(self.random_member());
+            }
+            return picked.into_iter().collect();
+        }
+        let mut members: Vec<Bytes> = self.iter().collect();
+        let count = count.min(members.len());
+        for i in 0..count {
+            let j = i + random_index(members.len() - i);
+            members.swap(i, j);
+        }
+        members.truncate(count);
+        members
+    }
+
+    /// One `SSCAN` step. Like Redis, an intset is returned whole in a single
+    /// call, with cursor 0.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(Bytes)) -> u64 {
+        match self {
+            Set::Intset(_) => {
+                self.iter().for_each(visit);
+                0
+            }
+            Set::Table(table) => table.scan_many(cursor, count, |member, _| visit(member.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Set;
+
+    #[test]
+    fn intset_until_a_string_or_the_limit() {
+        let mut set = Set::new();
+        assert!(set.insert("3".into(), 3));
+        assert!(set.insert("-1".into(), 3));
+        assert!(!set.insert("3".into(), 3));
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set.iter().collect::<Vec<_>>(), ["-1", "3"]);
+        // Not canonical, so not an integer member.
+        assert!(!set.contains(b"03"));
+        assert!(set.insert("03".into(), 3));
+        assert_eq!(set.encoding(), "hashtable");
+        assert!(set.contains(b"3") && set.contains(b"03"));
+
+        let mut set = Set::from_members(["1".into(), "2".into()], 2);
+        assert_eq!(set.encoding(), "intset");
+        set.insert("4".into(), 2);
+        assert_eq!(set.encoding(), "hashtable");
+        assert!(set.remove(b"1"));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn random_distinct_members() {
+        let set = Set::from_members((0..100).map(|i| format!("m{}", i).into()), 512);
+        for count in [0, 5, 40, 100, 200] {
+            let mut picked = set.random_distinct(count);
+            assert_eq!(picked.len(), count.min(100));
+            assert!(picked.iter().all(|member| set.contains(member)));
+            picked.sort();
+            picked.dedup();
+            assert_eq!(picked.len(), count.min(100));
+        }
+    }
+}
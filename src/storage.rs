@@ -1,11 +1,12 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     fmt::format,
     path::Path,
     sync::Arc,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use bytes::{Bytes, BytesMut};
 use regex::Regex;
 use tokio::{
     fs::File,
@@ -14,63 +15,481 @@ use tokio::{
 };
 
 use crate::{
+    blocking::Blocking,
+    commands::{generic::unix_millis, string::parse_i64},
     config::Config,
-    resp::{resp::Value, RespError}, server::unpack_bulk_string,
+    dict::Dict,
+    glob::glob_match,
+    hash::Hash,
+    resp::{resp::Value, RespError},
+    set::Set,
+    stream::{Consumer, ConsumerGroup, PendingEntry, Stream, StreamId},
+    zset::SortedSet,
 };
-#[derive(Debug)]
+/// A value stored in the keyspace, tagged with its Redis type.
+#[derive(Clone, Debug)]
+pub enum RedisValue {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(SortedSet),
+    Stream(Stream),
+}
+
+#[derive(Clone, Debug)]
 pub(crate) struct Item {
-    pub value: Value,
+    pub value: RedisValue,
     pub ttl: Option<SystemTime>,
 }
 
+impl Item {
+    pub fn new(value: RedisValue) -> Self {
+        Self { value, ttl: None }
+    }
+
+    /// The name `TYPE` reports for this item.
+    pub fn type_name(&self) -> &'static str {
+        match self.value {
+            RedisValue::String(_) => "string",
+            RedisValue::List(_) => "list",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Set(_) => "set",
+            RedisValue::ZSet(_) => "zset",
+            RedisValue::Stream(_) => "stream",
+        }
+    }
+
+    /// The name `OBJECT ENCODING` reports for this item.
+    pub fn encoding(&self) -> &'static str {
+        match &self.value {
+            RedisValue::String(value) if value.len() <= 20 && parse_i64(value).is_ok() => "int",
+            RedisValue::String(value) if value.len() <= 44 => "embstr",
+            RedisValue::String(_) => "raw",
+            RedisValue::List(_) => "quicklist",
+            RedisValue::Hash(hash) => hash.encoding(),
+            RedisValue::Set(set) => set.encoding(),
+            RedisValue::ZSet(_) => "skiplist",
+            RedisValue::Stream(_) => "stream",
+        }
+    }
+
+    /// Rough number of allocations freeing this item costs, used to decide
+    /// whether `UNLINK` frees it in the background.
+    pub fn free_effort(&self) -> usize {
+        match &self.value {
+            RedisValue::String(_) => 1,
+            RedisValue::List(list) => list.len(),
+            RedisValue::Hash(hash) => hash.len(),
+            RedisValue::Set(set) => set.len(),
+            RedisValue::ZSet(zset) => zset.len(),
+            RedisValue::Stream(stream) => stream.node_count(),
+        }
+    }
+}
+
+/// Counters reported by `INFO stats`.
+#[derive(Default)]
+pub struct Stats {
+    pub expired_keys: u64,
+    pub expired_time_cap_reached_count: u64,
+    /// Hash fields deleted because their own TTL passed.
+    pub expired_subkeys: u64,
+}
+
 pub struct Storage {
-    pub storage: HashMap<String, Item>,
+    pub storage: Dict<Bytes, Item>,
+    pub stats: Stats,
+    pub blocking: Blocking,
+    /// Where the next active expiry cycle resumes scanning the keyspace.
+    expire_cursor: u64,
+}
+
+/// Keys with a TTL sampled per active expiry loop, like Redis'
+/// `ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP`.
+const EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// Sampling continues while more than this percentage of the sampled keys
+/// turned out to be expired.
+const EXPIRE_ACCEPTABLE_STALE: usize = 10;
+
+fn is_expired(item: &Item, now: SystemTime) -> bool {
+    item.ttl.is_some_and(|ttl| now >= ttl)
 }
 
 impl Storage {
     pub fn new() -> Self {
         Self {
-            storage: HashMap::new(),
+            storage: Dict::new(),
+            stats: Stats::default(),
+            blocking: Blocking::default(),
+            expire_cursor: 0,
         }
     }
 
-    pub async fn set(&mut self, key: String, value: String, ttl: Option<SystemTime>) -> Value {
+    pub fn set(&mut self, key: Bytes, value: Bytes, ttl: Option<SystemTime>) -> Value {
         // println!("key {}\nvalue {}\nexpies {:?}", key, value, ttl);
         self.storage.insert(
             key,
             Item {
-                value: Value::BulkString(value),
+                value: RedisValue::String(value),
                 ttl,
             },
         );
         Value::SimpleString("OK".to_owned())
     }
 
-    pub async fn get(&self, key: String) -> Value {
-        match self.storage.get(&key) {
-            Some(item) => {
-                if let Some(is_expired) = item.ttl {
-                    if SystemTime::now() >= is_expired {
-                        return Value::Null;
+    /// Deletes `key` if its TTL has passed, returning whether it did. Also
+    /// drops expired hash fields, deleting the hash if none are left.
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        let now = SystemTime::now();
+        let Some(item) = self.storage.get_mut(key) else {
+            return false;
+        };
+        if is_expired(item, now) {
+            self.storage.remove(key);
+            self.stats.expired_keys += 1;
+            return true;
+        }
+        self.expire_fields(key, now)
+    }
+
+    /// Drops the expired fields of the hash at `key`, deleting it once empty,
+    /// and returns whether the key went away.
+    fn expire_fields(&mut self, key: &[u8], now: SystemTime) -> bool {
+        let Some(Item {
+            value: RedisValue::Hash(hash),
+            ..
+        }) = self.storage.get_mut(key)
+        else {
+            return false;
+        };
+        let expired = hash.purge_expired(now);
+        if expired == 0 {
+            return false;
+        }
+        self.stats.expired_subkeys += expired as u64;
+        let emptied = hash.is_empty();
+        if emptied {
+            self.storage.remove(key);
+        }
+        emptied
+    }
+
+    /// The live item stored at `key`, if any. Expired items found on the way
+    /// are deleted.
+    pub fn lookup(&mut self, key: &[u8]) -> Option<&Item> {
+        self.expire_if_needed(key);
+        self.storage.get(key)
+    }
+
+    pub fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut Item> {
+        self.expire_if_needed(key);
+        self.storage.get_mut(key)
+    }
+
+    /// The string stored at `key`, or WRONGTYPE if it holds something else.
+    pub fn get_string(&mut self, key: &[u8]) -> Result<Option<Bytes>, RespError> {
+        match self.lookup(key).map(|item| &item.value) {
+            None => Ok(None),
+            Some(RedisValue::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Edits the string stored at `key` in place, starting from an empty
+    /// one if the key is missing, or fails with WRONGTYPE if it holds
+    /// something else. The bytes are only copied if a reply still shares
+    /// them, so growing a string a little at a time stays cheap.
+    pub fn with_string_mut<R>(
+        &mut self,
+        key: &Bytes,
+        edit: impl FnOnce(&mut BytesMut) -> R,
+    ) -> Result<R, RespError> {
+        if self.lookup(key).is_none() {
+            self.set(key.clone(), Bytes::new(), None);
+        }
+        let Some(Item {
+            value: RedisValue::String(value),
+            ..
+        }) = self.storage.get_mut(&key[..])
+        else {
+            return Err(RespError::WrongType);
+        };
+        let mut bytes = std::mem::take(value)
+            .try_into_mut()
+            .unwrap_or_else(|shared| BytesMut::from(&shared[..]));
+        let result = edit(&mut bytes);
+        *value = bytes.freeze();
+        Ok(result)
+    }
+
+    /// The list stored at `key`, or WRONGTYPE if it holds something else.
+    pub fn get_list(&mut self, key: &[u8]) -> Result<Option<&mut VecDeque<Bytes>>, RespError> {
+        match self.lookup_mut(key).map(|item| &mut item.value) {
+            None => Ok(None),
+            Some(RedisValue::List(list)) => Ok(Some(list)),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Like [`Storage::get_list`], creating an empty list if `key` is missing.
+    pub fn get_or_create_list(&mut self, key: &Bytes) -> Result<&mut VecDeque<Bytes>, RespError> {
+        if self.lookup(key).is_none() {
+            self.insert(key.clone(), Item::new(RedisValue::List(VecDeque::new())));
+        }
+        Ok(self.get_list(key)?.expect("list was just created"))
+    }
+
+    /// The hash stored at `key`, or WRONGTYPE if it holds something else.
+    pub fn get_hash(&mut self, key: &[u8]) -> Result<Option<&mut Hash>, RespError> {
+        match self.lookup_mut(key).map(|item| &mut item.value) {
+            None => Ok(None),
+            Some(RedisValue::Hash(hash)) => Ok(Some(hash)),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Like [`Storage::get_hash`], creating an empty hash if `key` is missing.
+    pub fn get_or_create_hash(&mut self, key: &Bytes) -> Result<&mut Hash, RespError> {
+        if self.lookup(key).is_none() {
+            self.insert(key.clone(), Item::new(RedisValue::Hash(Hash::new())));
+        }
+        Ok(self.get_hash(key)?.expect("hash was just created"))
+    }
+
+    /// The set stored at `key`, or WRONGTYPE if it holds something else.
+    pub fn get_set(&mut self, key: &[u8]) -> Result<Option<&mut Set>, RespError> {
+        match self.lookup_mut(key).map(|item| &mut item.value) {
+            None => Ok(None),
+            Some(RedisValue::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Like [`Storage::get_set`], creating an empty set if `key` is missing.
+    pub fn get_or_create_set(&mut self, key: &Bytes) -> Result<&mut Set, RespError> {
+        if self.lookup(key).is_none() {
+            self.insert(key.clone(), Item::new(RedisValue::Set(Set::new())));
+        }
+        Ok(self.get_set(key)?.expect("set was just created"))
+    }
+
+    /// The sorted set stored at `key`, or WRONGTYPE if it holds something
+    /// else.
+    pub fn get_zset(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>, RespError> {
+        match self.lookup_mut(key).map(|item| &mut item.value) {
+            None => Ok(None),
+            Some(RedisValue::ZSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Like [`Storage::get_zset`], creating an empty sorted set if `key` is
+    /// missing.
+    pub fn get_or_create_zset(&mut self, key: &Bytes) -> Result<&mut SortedSet, RespError> {
+        if self.lookup(key).is_none() {
+            self.insert(key.clone(), Item::new(RedisValue::ZSet(SortedSet::new())));
+        }
+        Ok(self.get_zset(key)?.expect("sorted set was just created"))
+    }
+
+    /// The stream stored at `key`, or WRONGTYPE if it holds something else.
+    pub fn get_stream(&mut self, key: &[u8]) -> Result<Option<&mut Stream>, RespError> {
+        match self.lookup_mut(key).map(|item| &mut item.value) {
+            None => Ok(None),
+            Some(RedisValue::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(RespError::WrongType),
+        }
+    }
+
+    /// Like [`Storage::get_stream`], creating an empty stream if `key` is
+    /// missing. Unlike other types, streams stay around once empty.
+    pub fn get_or_create_stream(&mut self, key: &Bytes) -> Result<&mut Stream, RespError> {
+        if self.lookup(key).is_none() {
+            self.insert(key.clone(), Item::new(RedisValue::Stream(Stream::new())));
+        }
+        Ok(self.get_stream(key)?.expect("stream was just created"))
+    }
+
+    /// The sets stored at each of `keys`, for commands that combine several
+    /// sets. Fails with WRONGTYPE if any key holds something else.
+    pub fn get_sets(&mut self, keys: &[Bytes]) -> Result<Vec<Option<&Set>>, RespError> {
+        for key in keys {
+            self.get_set(key)?;
+        }
+        Ok(keys
+            .iter()
+            .map(|key| match self.storage.get(key).map(|item| &item.value) {
+                Some(RedisValue::Set(set)) => Some(set),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Deletes `key` if it holds an empty collection; Redis never keeps
+    /// empty aggregates around.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        let empty = match self.storage.get(key).map(|item| &item.value) {
+            Some(RedisValue::List(list)) => list.is_empty(),
+            Some(RedisValue::Hash(hash)) => hash.is_empty(),
+            Some(RedisValue::Set(set)) => set.is_empty(),
+            Some(RedisValue::ZSet(zset)) => zset.is_empty(),
+            _ => false,
+        };
+        if empty {
+            self.storage.remove(key);
+        }
+    }
+
+    /// Stores a string at `key`, keeping the TTL of whatever lived there.
+    pub fn set_keep_ttl(&mut self, key: Bytes, value: Bytes) {
+        match self.lookup_mut(&key) {
+            Some(item) => item.value = RedisValue::String(value),
+            None => {
+                self.set(key, value, None);
+            }
+        }
+    }
+
+    /// Stores `item` at `key`, replacing whatever was there, and wakes up
+    /// clients blocked on `key`.
+    pub fn insert(&mut self, key: Bytes, item: Item) {
+        self.blocking.signal(&key);
+        self.storage.insert(key, item);
+    }
+
+    /// Number of keys, including expired ones that have not been reclaimed.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Number of keys with a TTL, for `INFO keyspace`.
+    pub fn expires_count(&self) -> usize {
+        self.storage
+            .values()
+            .filter(|item| item.ttl.is_some())
+            .count()
+    }
+
+    /// A random live key, or `None` if there are none.
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        // Expired keys are reclaimed as they are drawn, but give up after a
+        // few draws rather than spending forever on a mostly expired keyspace.
+        for _ in 0..100 {
+            let (key, _) = self.storage.random_entry()?;
+            let key = key.clone();
+            if !self.expire_if_needed(&key) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Deletes `key`, returning the live item it held. Like in Redis,
+    /// clients blocked reading a deleted stream are woken up to find out.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Item> {
+        self.expire_if_needed(key);
+        let item = self.storage.remove(key)?;
+        if matches!(item.value, RedisValue::Stream(_)) {
+            self.blocking.signal(key);
+        }
+        Some(item)
+    }
+
+    pub fn get(&mut self, key: Bytes) -> Result<Value, RespError> {
+        match self.lookup(&key) {
+            Some(item) => match &item.value {
+                RedisValue::String(value) => Ok(Value::BulkString(value.clone())),
+                _ => Err(RespError::WrongType),
+            },
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// One run of the active expiry cycle, modelled on Redis'
+    /// `activeExpireCycle`: keys are sampled by scanning the keyspace from
+    /// where the previous run stopped, 20 keys with a TTL at a time, and
+    /// sampling goes on while a sizeable share of them were expired and the
+    /// time budget allows.
+    pub fn active_expire_cycle(&mut self, time_limit: Duration) {
+        let started = Instant::now();
+        loop {
+            let now = SystemTime::now();
+            let (mut sampled, mut buckets) = (0, 0);
+            let mut expired = Vec::new();
+            let mut with_field_ttls = Vec::new();
+            while sampled < EXPIRE_KEYS_PER_LOOP && buckets < EXPIRE_KEYS_PER_LOOP * 20 {
+                self.expire_cursor = self.storage.scan(self.expire_cursor, |key, item| {
+                    if matches!(&item.value, RedisValue::Hash(hash) if hash.has_field_ttls()) {
+                        with_field_ttls.push(key.clone());
                     }
-                };
-                return item.value.clone();
+                    if item.ttl.is_some() {
+                        sampled += 1;
+                        if is_expired(item, now) {
+                            expired.push(key.clone());
+                        }
+                    }
+                });
+                buckets += 1;
+                if self.expire_cursor == 0 {
+                    break;
+                }
+            }
+
+            for key in &expired {
+                self.storage.remove(key);
+            }
+            self.stats.expired_keys += expired.len() as u64;
+            // Hash fields are not part of the sampling statistics; every
+            // hash with field TTLs is purged as the scan passes over it.
+            for key in &with_field_ttls {
+                self.expire_fields(key, now);
+            }
+
+            if sampled == 0 || expired.len() * 100 <= sampled * EXPIRE_ACCEPTABLE_STALE {
+                return;
+            }
+            if started.elapsed() > time_limit {
+                self.stats.expired_time_cap_reached_count += 1;
+                return;
             }
-            None => Value::Null,
         }
     }
-    pub fn keys(&self, pattern: String) -> Value {
-        let keys = self.storage.keys().cloned();
-        let key_resp = keys
-            .filter(|key| key.contains(&pattern.replace("*", "")))
-            .map(|key| Value::BulkString(key))
+
+    /// One `SCAN` step: walks buckets from `cursor` until at least `count`
+    /// keys were visited (or a bounded number of buckets were scanned), and
+    /// returns the next cursor with the live keys accepted by `filter`.
+    pub fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        filter: impl Fn(&Bytes, &Item) -> bool,
+    ) -> (u64, Vec<Bytes>) {
+        let now = SystemTime::now();
+        let mut keys = Vec::new();
+        let cursor = self.storage.scan_many(cursor, count, |key, item| {
+            if !is_expired(item, now) && filter(key, item) {
+                keys.push(key.clone());
+            }
+        });
+        (cursor, keys)
+    }
+
+    /// Live keys matching the glob `pattern`.
+    pub fn keys(&self, pattern: &[u8]) -> Value {
+        let now = SystemTime::now();
+        let key_resp = self
+            .storage
+            .iter()
+            .filter(|(key, item)| !is_expired(item, now) && glob_match(pattern, key, false))
+            .map(|(key, _)| Value::BulkString(key.clone()))
             .collect::<Vec<Value>>();
 
         Value::Array(key_resp)
     }
     pub async fn save_to_rdb(&self, config: &Config) -> Result<(), RespError> {
         if !config.has_rdb() {
-            return Err(RespError::Other(format!("no rdb file configured")));
+            return Err(RespError::Other("no rdb file configured".to_string()));
         }
 
         let map_rdb_err = |e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e));
@@ -86,20 +505,24 @@ impl Storage {
         writer.write_u8(0xFE).await.map_err(map_rdb_err)?;
         writer.write_u32_le(0).await.map_err(map_rdb_err)?;
 
+        // Keys that expired but were not reclaimed yet are not persisted.
+        let now = SystemTime::now();
+        let live = self
+            .storage
+            .iter()
+            .filter(|(_, item)| !is_expired(item, now))
+            .collect::<Vec<_>>();
+
         writer.write_u8(0xFB).await.map_err(map_rdb_err)?;
-        write_length(&mut writer, self.storage.len() as u64)
+        write_length(&mut writer, live.len() as u64)
             .await
             .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
-        let expires_count = self
-            .storage
-            .values()
-            .filter(|item| item.ttl.is_some())
-            .count();
+        let expires_count = live.iter().filter(|(_, item)| item.ttl.is_some()).count();
         write_length(&mut writer, expires_count as u64)
             .await
             .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
 
-        for (key, item) in &self.storage {
+        for (key, item) in live {
             if let Some(expiry_time) = item.ttl {
                 let duration = expiry_time
                     .duration_since(UNIX_EPOCH)
@@ -112,20 +535,16 @@ impl Storage {
                     .map_err(map_rdb_err)?;
             }
 
-            writer.write_u8(0x00).await.map_err(map_rdb_err)?;
+            writer
+                .write_u8(rdb_type(&item.value))
+                .await
+                .map_err(map_rdb_err)?;
 
-            write_string(&mut writer, key.as_bytes())
+            write_string(&mut writer, key)
                 .await
                 .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
 
-            write_string(
-                &mut writer,
-                unpack_bulk_string(item.value.clone())
-                    .unwrap()
-                    .as_bytes(),
-            )
-            .await
-            .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
+            write_value(&mut writer, &item.value).await?;
         }
 
         writer.write_u8(0xFF).await.map_err(map_rdb_err)?;
@@ -142,13 +561,13 @@ impl Storage {
             return Ok(());
         }
 
-        let file = File::open(&path).await.map_err(|e| RespError::Io(e))?;
+        let file = File::open(&path).await.map_err(RespError::Io)?;
         let mut reader = BufReader::new(file);
         let mut header = [0u8; 9];
         reader
             .read_exact(&mut header)
             .await
-            .map_err(|e| RespError::Io(e))?;
+            .map_err(RespError::Io)?;
 
         let magic = &header[0..5];
         let version = &header[5..9];
@@ -170,12 +589,7 @@ impl Storage {
         }
         let mut expiry: Option<SystemTime> = None;
 
-        loop {
-            let opcode = match reader.read_u8().await {
-                Ok(byte) => byte,
-                Err(_) => break,
-            };
-
+        while let Ok(opcode) = reader.read_u8().await {
             match opcode {
                 0xFA => {
                     let _aux_key = read_string(&mut reader).await?;
@@ -212,23 +626,19 @@ impl Storage {
                     continue;
                 }
                 0xFF => break,
-                value_type => match value_type {
-                    0x00 => {
-                        let key_bytes = read_string(&mut reader).await.map_err(|e| {
-                            RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
-                        })?;
-                        let value_bytes = read_string(&mut reader).await.map_err(|e| {
-                            RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
-                        })?;
-                        let key = String::from_utf8_lossy(&key_bytes).to_string();
-                        let value = String::from_utf8_lossy(&value_bytes).to_string();
-
-                        self.set(key, value, expiry).await;
-
+                value_type => {
+                    let key_bytes = read_string(&mut reader).await.map_err(|e| {
+                        RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
+                    })?;
+                    let value = read_value(&mut reader, value_type, &config).await?;
+                    if matches!(&value, RedisValue::Hash(hash) if hash.is_empty()) {
                         expiry = None;
+                        continue;
                     }
-                    _ => unimplemented!("Unsupported value type: {}", value_type),
-                },
+                    self.insert(Bytes::from(key_bytes), Item { value, ttl: expiry });
+
+                    expiry = None;
+                }
             }
         }
 
@@ -236,6 +646,221 @@ impl Storage {
     }
 }
 
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_HASH: u8 = 4;
+/// `RDB_TYPE_ZSET_2`: members with their scores as binary doubles.
+const RDB_TYPE_ZSET: u8 = 5;
+/// A stream, in a simpler layout than Redis' listpacks and radix tree: the
+/// entries, each as its ID, field count and fields, then the last ID, the
+/// count of entries ever added and the greatest deleted ID.
+///
+/// Codes from 200 on are this server's own, far from those Redis assigns,
+/// so files holding them are never mistaken for Redis' and a stream saved
+/// by Redis (`RDB_TYPE_STREAM_LISTPACKS*`) fails the load instead of being
+/// misread.
+const RDB_TYPE_STREAM: u8 = 200;
+/// A stream with its consumer groups: the `RDB_TYPE_STREAM` layout
+/// followed by the groups, each with its PEL and consumers.
+const RDB_TYPE_STREAM_GROUPS: u8 = 201;
+/// A hash with field TTLs: the smallest field deadline in milliseconds,
+/// then for each field its deadline as `deadline - smallest + 1` (0 for
+/// none) followed by the field and the value. Redis 7.4's
+/// `RDB_TYPE_HASH_METADATA` (24) is not read.
+const RDB_TYPE_HASH_METADATA: u8 = 202;
+
+fn rdb_type(value: &RedisValue) -> u8 {
+    match value {
+        RedisValue::String(_) => RDB_TYPE_STRING,
+        RedisValue::List(_) => RDB_TYPE_LIST,
+        RedisValue::Hash(hash) if hash.has_field_ttls() => RDB_TYPE_HASH_METADATA,
+        RedisValue::Hash(_) => RDB_TYPE_HASH,
+        RedisValue::Set(_) => RDB_TYPE_SET,
+        RedisValue::ZSet(_) => RDB_TYPE_ZSET,
+        RedisValue::Stream(_) => RDB_TYPE_STREAM_GROUPS,
+    }
+}
+
+async fn write_value(writer: &mut BufWriter<File>, value: &RedisValue) -> Result<(), RespError> {
+    match value {
+        RedisValue::String(string) => write_string(writer, string).await,
+        RedisValue::List(list) => {
+            write_length(writer, list.len() as u64).await?;
+            for element in list {
+                write_string(writer, element).await?;
+            }
+            Ok(())
+        }
+        RedisValue::Set(set) => {
+            write_length(writer, set.len() as u64).await?;
+            for member in set.iter() {
+                write_string(writer, &member).await?;
+            }
+            Ok(())
+        }
+        RedisValue::ZSet(zset) => {
+            write_length(writer, zset.len() as u64).await?;
+            for (member, score) in zset.iter() {
+                write_string(writer, member).await?;
+                writer
+                    .write_f64_le(score)
+                    .await
+                    .map_err(|e| RespError::Other(format!("Unable to write RDB file\n{:?}", e)))?;
+            }
+            Ok(())
+        }
+        RedisValue::Stream(stream) => {
+            write_length(writer, stream.len() as u64).await?;
+            for entry in stream.iter() {
+                write_stream_id(writer, entry.id).await?;
+                write_length(writer, entry.fields.len() as u64).await?;
+                for (field, value) in &entry.fields {
+                    write_string(writer, field).await?;
+                    write_string(writer, value).await?;
+                }
+            }
+            write_stream_id(writer, stream.last_id).await?;
+            write_length(writer, stream.entries_added).await?;
+            write_stream_id(writer, stream.max_deleted_id).await?;
+            write_stream_groups(writer, stream).await
+        }
+        RedisValue::Hash(hash) if hash.has_field_ttls() => {
+            let millis = |time| unix_millis(time) as u64;
+            let now = millis(SystemTime::now());
+            let fields: Vec<_> = hash
+                .iter()
+                .map(|(field, value)| (field, value, hash.expire_at(field).map(millis)))
+                .filter(|(_, _, deadline)| deadline.is_none_or(|deadline| deadline > now))
+                .collect();
+            let min = fields.iter().filter_map(|(_, _, deadline)| *deadline).min();
+            let min = min.unwrap_or(now);
+            writer
+                .write_u64_le(min)
+                .await
+                .map_err(|e| RespError::Other(format!("Unable to write RDB file\n{:?}", e)))?;
+            write_length(writer, fields.len() as u64).await?;
+            for (field, value, deadline) in fields {
+                write_length(writer, deadline.map_or(0, |deadline| deadline - min + 1)).await?;
+                write_string(writer, field).await?;
+                write_string(writer, value).await?;
+            }
+            Ok(())
+        }
+        RedisValue::Hash(hash) => {
+            write_length(writer, hash.len() as u64).await?;
+            for (field, value) in hash.iter() {
+                write_string(writer, field).await?;
+                write_string(writer, value).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn read_value(
+    reader: &mut BufReader<File>,
+    value_type: u8,
+    config: &Config,
+) -> Result<RedisValue, RespError> {
+    let hash_limits = config.hash_limits();
+    match value_type {
+        RDB_TYPE_STRING => Ok(RedisValue::String(read_string(reader).await?.into())),
+        RDB_TYPE_LIST => {
+            let len = read_length(reader).await?;
+            let mut list = VecDeque::with_capacity(len.min(1024) as usize);
+            for _ in 0..len {
+                list.push_back(read_string(reader).await?.into());
+            }
+            Ok(RedisValue::List(list))
+        }
+        RDB_TYPE_SET => {
+            let len = read_length(reader).await?;
+            let mut members = Vec::with_capacity(len.min(1024) as usize);
+            for _ in 0..len {
+                members.push(Bytes::from(read_string(reader).await?));
+            }
+            Ok(RedisValue::Set(Set::from_members(
+                members,
+                config.set_max_intset(),
+            )))
+        }
+        RDB_TYPE_ZSET => {
+            let len = read_length(reader).await?;
+            let mut zset = SortedSet::new();
+            for _ in 0..len {
+                let member = read_string(reader).await?.into();
+                let score = reader
+                    .read_f64_le()
+                    .await
+                    .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
+                zset.insert(member, score);
+            }
+            Ok(RedisValue::ZSet(zset))
+        }
+        RDB_TYPE_STREAM | RDB_TYPE_STREAM_GROUPS => {
+            let len = read_length(reader).await?;
+            let mut stream = Stream::new();
+            for _ in 0..len {
+                let id = read_stream_id(reader).await?;
+                let count = read_length(reader).await?;
+                let mut fields = Vec::with_capacity(count.min(1024) as usize);
+                for _ in 0..count {
+                    let field = read_string(reader).await?.into();
+                    fields.push((field, read_string(reader).await?.into()));
+                }
+                stream.append(id, fields, config.stream_node_max());
+            }
+            stream.last_id = read_stream_id(reader).await?;
+            stream.entries_added = read_length(reader).await?;
+            stream.max_deleted_id = read_stream_id(reader).await?;
+            if value_type == RDB_TYPE_STREAM_GROUPS {
+                read_stream_groups(reader, &mut stream).await?;
+            }
+            Ok(RedisValue::Stream(stream))
+        }
+        RDB_TYPE_HASH => {
+            let len = read_length(reader).await?;
+            let mut pairs = Vec::with_capacity(len.min(1024) as usize);
+            for _ in 0..len {
+                let field = read_string(reader).await?.into();
+                pairs.push((field, read_string(reader).await?.into()));
+            }
+            Ok(RedisValue::Hash(Hash::from_pairs(pairs, hash_limits)))
+        }
+        RDB_TYPE_HASH_METADATA => {
+            let min = reader
+                .read_u64_le()
+                .await
+                .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e)))?;
+            let len = read_length(reader).await?;
+            let mut pairs = Vec::with_capacity(len.min(1024) as usize);
+            let mut deadlines = Vec::new();
+            for _ in 0..len {
+                let deadline = read_length(reader).await?;
+                let field: Bytes = read_string(reader).await?.into();
+                if deadline > 0 {
+                    let deadline = UNIX_EPOCH + Duration::from_millis(min + deadline - 1);
+                    deadlines.push((field.clone(), deadline));
+                }
+                pairs.push((field, read_string(reader).await?.into()));
+            }
+            let mut hash = Hash::from_pairs(pairs, hash_limits);
+            for (field, deadline) in deadlines {
+                hash.set_expire(&field, Some(deadline));
+            }
+            // Fields that expired while the server was down are dropped
+            // here; if that empties the hash, the loader skips the key.
+            hash.purge_expired(SystemTime::now());
+            Ok(RedisValue::Hash(hash))
+        }
+        _ => Err(RespError::Other(format!(
+            "Unsupported value type: {}",
+            value_type
+        ))),
+    }
+}
+
 async fn write_length(writer: &mut BufWriter<File>, length: u64) -> Result<(), RespError> {
     let map_rdb_err = |e| RespError::Other(format!("Unable to write RDB file\n{:?}", e));
 
@@ -247,13 +872,123 @@ async fn write_length(writer: &mut BufWriter<File>, length: u64) -> Result<(), R
         writer.write_u8(first_byte).await.map_err(map_rdb_err)?;
 
         writer.write_u8(second_byte).await.map_err(map_rdb_err)?;
-    } else {
+    } else if length <= u32::MAX as u64 {
         writer.write_u8(0b10000000).await.map_err(map_rdb_err)?;
 
-        writer
-            .write_u32_le(length as u32)
-            .await
-            .map_err(map_rdb_err)?;
+        writer.write_u32(length as u32).await.map_err(map_rdb_err)?;
+    } else {
+        writer.write_u8(0b10000001).await.map_err(map_rdb_err)?;
+        writer.write_u64(length).await.map_err(map_rdb_err)?;
+    }
+    Ok(())
+}
+
+async fn write_stream_id(writer: &mut BufWriter<File>, id: StreamId) -> Result<(), RespError> {
+    write_length(writer, id.ms).await?;
+    write_length(writer, id.seq).await
+}
+
+async fn read_stream_id(reader: &mut BufReader<File>) -> Result<StreamId, RespError> {
+    let ms = read_length(reader).await?;
+    Ok(StreamId::new(ms, read_length(reader).await?))
+}
+
+/// Writes each group as its name, last delivered ID and entries read, then
+/// its PEL as IDs with delivery times and counts, then its consumers as
+/// names with seen and active times and the IDs pending with them. Optional
+/// numbers are written as lengths, 0 standing for none and `n + 1` for `n`.
+async fn write_stream_groups(
+    writer: &mut BufWriter<File>,
+    stream: &Stream,
+) -> Result<(), RespError> {
+    let map_rdb_err = |e| RespError::Other(format!("Unable to write RDB file\n{:?}", e));
+    let optional = |value: Option<u64>| value.map_or(0, |value| value + 1);
+
+    write_length(writer, stream.groups.len() as u64).await?;
+    for (name, group) in &stream.groups {
+        write_string(writer, name).await?;
+        write_stream_id(writer, group.last_delivered).await?;
+        write_length(writer, optional(group.entries_read)).await?;
+        write_length(writer, group.pending.len() as u64).await?;
+        for (&id, pending) in &group.pending {
+            write_stream_id(writer, id).await?;
+            writer
+                .write_u64_le(pending.delivery_time)
+                .await
+                .map_err(map_rdb_err)?;
+            write_length(writer, pending.delivery_count).await?;
+        }
+        write_length(writer, group.consumers.len() as u64).await?;
+        for (name, consumer) in &group.consumers {
+            write_string(writer, name).await?;
+            writer
+                .write_u64_le(consumer.seen_time)
+                .await
+                .map_err(map_rdb_err)?;
+            write_length(writer, optional(consumer.active_time)).await?;
+            write_length(writer, consumer.pending.len() as u64).await?;
+            for &id in &consumer.pending {
+                write_stream_id(writer, id).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn read_stream_groups(
+    reader: &mut BufReader<File>,
+    stream: &mut Stream,
+) -> Result<(), RespError> {
+    let map_rdb_err = |e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e));
+    let optional = |value: u64| value.checked_sub(1);
+
+    let groups = read_length(reader).await?;
+    for _ in 0..groups {
+        let name: Bytes = read_string(reader).await?.into();
+        let last_delivered = read_stream_id(reader).await?;
+        let entries_read = optional(read_length(reader).await?);
+        let mut group = ConsumerGroup::new(last_delivered, entries_read);
+        // The PEL comes first, so owners are filled in from the consumers.
+        let mut pending = BTreeMap::new();
+        for _ in 0..read_length(reader).await? {
+            let id = read_stream_id(reader).await?;
+            let delivery_time = reader.read_u64_le().await.map_err(map_rdb_err)?;
+            let delivery_count = read_length(reader).await?;
+            pending.insert(id, (delivery_time, delivery_count));
+        }
+        for _ in 0..read_length(reader).await? {
+            let consumer_name: Bytes = read_string(reader).await?.into();
+            let seen_time = reader.read_u64_le().await.map_err(map_rdb_err)?;
+            let active_time = optional(read_length(reader).await?);
+            let mut consumer = Consumer {
+                seen_time,
+                active_time,
+                pending: BTreeSet::new(),
+            };
+            for _ in 0..read_length(reader).await? {
+                let id = read_stream_id(reader).await?;
+                let Some((delivery_time, delivery_count)) = pending.remove(&id) else {
+                    return Err(RespError::Other(format!(
+                        "Unable to parse RDB file\nconsumer entry {} is not in the PEL",
+                        id
+                    )));
+                };
+                consumer.pending.insert(id);
+                let entry = PendingEntry {
+                    consumer: consumer_name.clone(),
+                    delivery_time,
+                    delivery_count,
+                };
+                group.pending.insert(id, entry);
+            }
+            group.consumers.insert(consumer_name, consumer);
+        }
+        if !pending.is_empty() {
+            return Err(RespError::Other(
+                "Unable to parse RDB file\nPEL entry without a consumer".to_owned(),
+            ));
+        }
+        stream.groups.insert(name, group);
     }
     Ok(())
 }
@@ -272,10 +1007,7 @@ async fn write_string(writer: &mut BufWriter<File>, bytes: &[u8]) -> Result<(),
         writer.write_u8(second_byte).await.map_err(map_rdb_err)?;
     } else {
         writer.write_u8(0b10000000).await.map_err(map_rdb_err)?;
-        writer
-            .write_u32_le(length as u32)
-            .await
-            .map_err(map_rdb_err)?;
+        writer.write_u32(length as u32).await.map_err(map_rdb_err)?;
     }
 
     writer.write_all(bytes).await.map_err(map_rdb_err)?;
@@ -306,6 +1038,10 @@ async fn read_length(reader: &mut BufReader<File>) -> Result<u64, RespError> {
             Ok(length)
         }
 
+        0b10 if first_byte == 0b10000001 => reader
+            .read_u64()
+            .await
+            .map_err(|e| RespError::Other(format!("Unable to parse RDB file\n{:?}", e))),
         0b10 => {
             let length = reader
                 .read_u32()
@@ -323,7 +1059,7 @@ async fn read_length(reader: &mut BufReader<File>) -> Result<u64, RespError> {
                 special_type
             )))
         }
-        _ => Err(RespError::Other(format!("Invalid length encoding"))),
+        _ => Err(RespError::Other("Invalid length encoding".to_string())),
     }
 }
 
@@ -336,7 +1072,7 @@ async fn read_string(reader: &mut BufReader<File>) -> Result<Vec<u8>, RespError>
     // println!("first_byte: {}\nlen: {}", first_byte, encoding_type);
 
     match encoding_type {
-        0b00 | 0b01 | 0b10 => {
+        0b00..=0b10 => {
             let length = match encoding_type {
                 0b00 => (first_byte & 0b00111111) as usize,
 
@@ -372,13 +1108,13 @@ async fn read_string(reader: &mut BufReader<File>) -> Result<Vec<u8>, RespError>
                     Ok(value.to_string().into_bytes())
                 }
                 1 => {
-                    let value = reader.read_i16().await.map_err(|e| {
+                    let value = reader.read_i16_le().await.map_err(|e| {
                         RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
                     })?;
                     Ok(value.to_string().into_bytes())
                 }
                 2 => {
-                    let value = reader.read_i32().await.map_err(|e| {
+                    let value = reader.read_i32_le().await.map_err(|e| {
                         RespError::Other(format!("Unable to parse RDB file\n{:?}", e))
                     })?;
                     Ok(value.to_string().into_bytes())
@@ -390,6 +1126,179 @@ async fn read_string(reader: &mut BufReader<File>) -> Result<Vec<u8>, RespError>
                 ))),
             }
         }
-        _ => Err(RespError::Other(format!("Unable to parse RDB file"))),
+        _ => Err(RespError::Other("Unable to parse RDB file".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use bytes::Bytes;
+
+    use super::{Item, RedisValue, Storage};
+    use crate::{
+        config::Config,
+        hash::{Hash, ListpackLimits},
+        stream::{ConsumerGroup, Stream, StreamId},
+    };
+
+    fn key(i: usize) -> Bytes {
+        format!("key:{}", i).into()
+    }
+
+    /// A configuration saving to a scratch RDB file called `name`.
+    fn scratch_rdb(name: &str) -> Arc<Config> {
+        let dir = std::env::temp_dir().join(format!("redis-rdb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Arc::new(Config::parse_from([
+            "redis-server",
+            "--dir",
+            dir.to_str().unwrap(),
+            "--dbfilename",
+            name,
+        ]))
+    }
+
+    /// Saves `db` to a scratch RDB file called `name` and loads it back.
+    async fn reload(db: &Storage, name: &str) -> Storage {
+        let config = scratch_rdb(name);
+        db.save_to_rdb(&config).await.unwrap();
+        let mut loaded = Storage::new();
+        loaded.load_from_rdb(Arc::clone(&config)).await.unwrap();
+        std::fs::remove_file(config.get_rdb_path().unwrap()).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn lookups_drop_expired_keys() {
+        let mut db = Storage::new();
+        let past = SystemTime::now() - Duration::from_secs(1);
+        let future = SystemTime::now() + Duration::from_secs(60);
+        db.set(key(0), "gone".into(), Some(past));
+        db.set(key(1), "kept".into(), Some(future));
+        assert_eq!(db.len(), 2);
+
+        assert!(db.lookup(&key(0)).is_none());
+        assert!(db.lookup(&key(1)).is_some());
+        assert_eq!(db.len(), 1);
+        assert_eq!(db.stats.expired_keys, 1);
+    }
+
+    #[test]
+    fn active_cycle_reclaims_keys_nobody_reads() {
+        let mut db = Storage::new();
+        let past = SystemTime::now() - Duration::from_secs(1);
+        for i in 0..1000 {
+            db.set(key(i), "gone".into(), Some(past));
+        }
+        for i in 1000..1100 {
+            db.set(key(i), "kept".into(), None);
+        }
+
+        // With every sampled key expired, a cycle keeps going until none
+        // are left.
+        db.active_expire_cycle(Duration::from_secs(10));
+        assert_eq!(db.len(), 100);
+        assert_eq!(db.stats.expired_keys, 1000);
+        assert_eq!(db.expires_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn streams_survive_a_restart() {
+        let mut stream = Stream::new();
+        for ms in 1..=3 {
+            let fields = vec![("n".into(), ms.to_string().into())];
+            stream.append(StreamId::new(ms, 0), fields, 2);
+        }
+        stream.remove(StreamId::new(2, 0));
+        let mut db = Storage::new();
+        db.insert("s".into(), Item::new(RedisValue::Stream(stream)));
+
+        let mut db = reload(&db, "streams.rdb").await;
+        let stream = db.get_stream(b"s").unwrap().unwrap();
+        let ids: Vec<_> = stream.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [StreamId::new(1, 0), StreamId::new(3, 0)]);
+        assert_eq!(stream.get(StreamId::new(3, 0)).unwrap().fields[0].1, "3");
+        assert_eq!(stream.last_id, StreamId::new(3, 0));
+        assert_eq!(stream.max_deleted_id, StreamId::new(2, 0));
+        assert_eq!(stream.entries_added, 3);
+    }
+
+    #[tokio::test]
+    async fn streams_saved_by_redis_fail_the_load() {
+        let config = scratch_rdb("redis.rdb");
+        let path = config.get_rdb_path().unwrap();
+        // `RDB_TYPE_STREAM_LISTPACKS` (15), whose layout is not supported.
+        std::fs::write(&path, b"REDIS0011\xfe\0\0\0\0\x0f\x01s\0").unwrap();
+        let error = Storage::new().load_from_rdb(config).await.unwrap_err();
+        assert_eq!(error.to_string(), "ERR Unsupported value type: 15");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn consumer_groups_survive_a_restart() {
+        let mut stream = Stream::new();
+        for ms in 1..=3 {
+            stream.append(StreamId::new(ms, 0), vec![("f".into(), "v".into())], 100);
+        }
+        let mut group = ConsumerGroup::new(StreamId::new(2, 0), Some(2));
+        group.deliver(StreamId::new(1, 0), &"alice".into(), 1000, 1);
+        group.deliver(StreamId::new(2, 0), &"bob".into(), 2000, 3);
+        group.create_consumer(&"carol".into(), 3000);
+        stream.groups.insert("readers".into(), group);
+        stream
+            .groups
+            .insert("idle".into(), ConsumerGroup::new(StreamId::default(), None));
+        let mut db = Storage::new();
+        db.insert("s".into(), Item::new(RedisValue::Stream(stream)));
+
+        let mut db = reload(&db, "groups.rdb").await;
+        let stream = db.get_stream(b"s").unwrap().unwrap();
+        assert_eq!(stream.len(), 3);
+        let idle = &stream.groups[b"idle".as_slice()];
+        assert_eq!(idle.entries_read, None);
+        assert!(idle.pending.is_empty() && idle.consumers.is_empty());
+
+        let group = &stream.groups[b"readers".as_slice()];
+        assert_eq!(group.last_delivered, StreamId::new(2, 0));
+        assert_eq!(group.entries_read, Some(2));
+        let pending = &group.pending[&StreamId::new(2, 0)];
+        assert_eq!(pending.consumer, "bob");
+        assert_eq!((pending.delivery_time, pending.delivery_count), (2000, 3));
+        let alice = &group.consumers[b"alice".as_slice()];
+        assert_eq!(
+            alice.pending.iter().collect::<Vec<_>>(),
+            [&StreamId::new(1, 0)]
+        );
+        assert_eq!(alice.active_time, Some(1000));
+        let carol = &group.consumers[b"carol".as_slice()];
+        assert_eq!((carol.seen_time, carol.active_time), (3000, None));
+        assert!(carol.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hash_field_ttls_survive_a_restart() {
+        let limits = ListpackLimits {
+            max_entries: 128,
+            max_value: 64,
+        };
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+        let mut hash = Hash::new();
+        for field in ["a", "b"] {
+            hash.insert(field.into(), "v".into(), limits);
+        }
+        hash.set_expire(&"a".into(), Some(later));
+        let mut db = Storage::new();
+        db.insert("h".into(), Item::new(RedisValue::Hash(hash)));
+
+        let mut db = reload(&db, "hash.rdb").await;
+        let hash = db.get_hash(b"h").unwrap().unwrap();
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.expire_at(b"a"), Some(later));
+        assert_eq!(hash.expire_at(b"b"), None);
     }
 }
@@ -0,0 +1,613 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use bytes::Bytes;
+
+/// A stream entry ID: a millisecond timestamp and a sequence number within
+/// that millisecond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match (self.ms, self.seq) {
+            (_, seq) if seq < u64::MAX => Some(StreamId::new(self.ms, seq + 1)),
+            (ms, _) if ms < u64::MAX => Some(StreamId::new(ms + 1, 0)),
+            _ => None,
+        }
+    }
+
+    /// The greatest ID smaller than this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match (self.ms, self.seq) {
+            (_, seq) if seq > 0 => Some(StreamId::new(self.ms, seq - 1)),
+            (ms, _) if ms > 0 => Some(StreamId::new(ms - 1, u64::MAX)),
+            _ => None,
+        }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` taking `missing_seq` as the sequence.
+    pub fn parse(arg: &[u8], missing_seq: u64) -> Option<StreamId> {
+        match arg.iter().position(|&byte| byte == b'-') {
+            Some(dash) => Some(StreamId::new(
+                Self::parse_part(&arg[..dash])?,
+                Self::parse_part(&arg[dash + 1..])?,
+            )),
+            None => Some(StreamId::new(Self::parse_part(arg)?, missing_seq)),
+        }
+    }
+
+    /// Parses one half of an ID, which must be a plain unsigned number.
+    pub fn parse_part(digits: &[u8]) -> Option<u64> {
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse::<u64>().ok()
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub id: StreamId,
+    pub fields: Vec<(Bytes, Bytes)>,
+}
+
+/// What `MAXLEN` or `MINID` trims a stream down to.
+#[derive(Clone, Copy, Debug)]
+pub enum Threshold {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// A trimming request. Approximate trims (`~`) only ever drop whole nodes,
+/// and stop after `limit` entries.
+#[derive(Clone, Copy, Debug)]
+pub struct Trim {
+    pub threshold: Threshold,
+    pub approx: bool,
+    pub limit: Option<usize>,
+}
+
+/// An entry delivered to a consumer of a group and not acknowledged yet.
+#[derive(Clone, Debug)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Consumer {
+    /// Unix time in milliseconds of the last read or claim attempt.
+    pub seen_time: u64,
+    /// Unix time in milliseconds of the last successful one, if any.
+    pub active_time: Option<u64>,
+    pub pending: BTreeSet<StreamId>,
+}
+
+/// A consumer group: how far it has read the stream, and which entries are
+/// pending with which of its consumers (the PEL).
+#[derive(Clone, Debug)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    /// Number of entries read, counting from the first ever added, when it
+    /// is known.
+    pub entries_read: Option<u64>,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId, entries_read: Option<u64>) -> Self {
+        Self {
+            last_delivered,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// Creates the consumer `name` unless it exists, returning whether it
+    /// was created.
+    pub fn create_consumer(&mut self, name: &Bytes, now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen_time: now,
+            active_time: None,
+            pending: BTreeSet::new(),
+        };
+        self.consumers.insert(name.clone(), consumer);
+        true
+    }
+
+    /// The consumer `name`, created if needed and marked as seen at `now`.
+    pub fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        self.create_consumer(name, now);
+        let consumer = self
+            .consumers
+            .get_mut(name)
+            .expect("consumer was just created");
+        consumer.seen_time = now;
+        consumer
+    }
+
+    /// Deletes a consumer along with its pending entries, returning how
+    /// many it had.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Records `id` as delivered to `consumer` at `now`, taking it from
+    /// whichever consumer held it before.
+    pub fn deliver(
+        &mut self,
+        id: StreamId,
+        consumer: &Bytes,
+        now: u64,
+        delivery_count: u64,
+    ) -> &mut PendingEntry {
+        let previous = self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.clone(),
+                delivery_time: now,
+                delivery_count,
+            },
+        );
+        if let Some(previous) = previous {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        let consumer = self.consumer(consumer, now);
+        consumer.active_time = Some(now);
+        consumer.pending.insert(id);
+        self.pending.get_mut(&id).expect("entry was just inserted")
+    }
+
+    /// Acknowledges `id`, returning whether it was pending.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        let Some(pending) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+}
+
+/// The value of a stream key.
+///
+/// Entries are kept in ID order in nodes of at most
+/// `stream-node-max-entries` entries, indexed by the ID each node started
+/// with, the way Redis keeps listpacks in a radix tree. Deleting entries
+/// leaves that key in place, which is fine for seeking: it is never greater
+/// than the node's remaining entries nor than any entry of the next node.
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    nodes: BTreeMap<StreamId, Vec<Entry>>,
+    len: usize,
+    /// The greatest ID ever added, even if since deleted.
+    pub last_id: StreamId,
+    pub max_deleted_id: StreamId,
+    /// Count of entries ever added, for `XINFO`.
+    pub entries_added: u64,
+    pub groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of nodes, for `UNLINK`'s free effort estimate.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The ID `*` stands for at `now_ms`: the current time, or the last ID's
+    /// time with the next sequence if the clock has not moved past it.
+    pub fn next_auto_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Appends an entry, whose ID must be greater than `last_id`.
+    pub fn append(&mut self, id: StreamId, fields: Vec<(Bytes, Bytes)>, node_max_entries: usize) {
+        let entry = Entry { id, fields };
+        match self.nodes.last_entry() {
+            Some(mut node) if node_max_entries == 0 || node.get().len() < node_max_entries => {
+                node.get_mut().push(entry)
+            }
+            _ => {
+                self.nodes.insert(id, vec![entry]);
+            }
+        }
+        self.len += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn first_entry(&self) -> Option<&Entry> {
+        self.nodes.values().next().and_then(|node| node.first())
+    }
+
+    pub fn last_entry(&self) -> Option<&Entry> {
+        self.nodes.values().next_back().and_then(|node| node.last())
+    }
+
+    /// The key of the node that would hold `id`.
+    fn node_key(&self, id: StreamId) -> Option<StreamId> {
+        self.nodes.range(..=id).next_back().map(|(&key, _)| key)
+    }
+
+    /// Entries with IDs between `start` and `end`, inclusive, in order.
+    pub fn range(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = &Entry> + '_ {
+        let from = self.node_key(start).unwrap_or(start);
+        self.nodes
+            .range(from..)
+            .flat_map(|(_, node)| node.iter())
+            .skip_while(move |entry| entry.id < start)
+            .take_while(move |entry| entry.id <= end)
+    }
+
+    /// Entries with IDs between `start` and `end`, inclusive, from the last.
+    pub fn rev_range(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = &Entry> + '_ {
+        self.nodes
+            .range(..=end)
+            .rev()
+            .flat_map(|(_, node)| node.iter().rev())
+            .skip_while(move |entry| entry.id > end)
+            .take_while(move |entry| entry.id >= start)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.nodes.values().flatten()
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Entry> {
+        let node = self.nodes.get(&self.node_key(id)?)?;
+        let position = node.binary_search_by_key(&id, |entry| entry.id).ok()?;
+        Some(&node[position])
+    }
+
+    /// Deletes the entry `id`, returning whether it existed.
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        let Some(key) = self.node_key(id) else {
+            return false;
+        };
+        let node = self.nodes.get_mut(&key).expect("key was just found");
+        let Ok(position) = node.binary_search_by_key(&id, |entry| entry.id) else {
+            return false;
+        };
+        node.remove(position);
+        if node.is_empty() {
+            self.nodes.remove(&key);
+        }
+        self.len -= 1;
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    /// Whether entries were deleted between `start` and the last entry,
+    /// which makes counting entries by ID unreliable.
+    fn has_tombstones_from(&self, start: StreamId) -> bool {
+        self.len > 0
+            && self.max_deleted_id != StreamId::MIN
+            && start <= self.max_deleted_id
+            && self.max_deleted_id <= self.last_id
+    }
+
+    /// How many entries up to `id` were ever added, when that can be told
+    /// without walking the stream, like Redis'
+    /// `streamEstimateDistanceFromFirstEverEntry`.
+    pub fn entries_read_at(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.len == 0 && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return Some(self.entries_added),
+            std::cmp::Ordering::Greater => return None,
+            std::cmp::Ordering::Less => {}
+        }
+        let first = self.first_entry().map_or(self.last_id, |entry| entry.id);
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first {
+            // Nothing was deleted past the first entry.
+            let before_first = self.entries_added - self.len as u64;
+            if id < first {
+                return Some(before_first);
+            }
+            if id == first {
+                return Some(before_first + 1);
+            }
+        }
+        None
+    }
+
+    /// Number of entries `group` has yet to read, if it can be told.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(group.last_delivered) => Some(read),
+            _ => self.entries_read_at(group.last_delivered),
+        };
+        entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    /// Hands `consumer` up to `count` entries its group has not read yet,
+    /// adding them to the PEL unless `noack`.
+    pub fn deliver_new(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        noack: bool,
+        now: u64,
+    ) -> Vec<Entry> {
+        let Some(start) = self.groups[group].last_delivered.next() else {
+            return vec![];
+        };
+        let entries: Vec<Entry> = self
+            .range(start, StreamId::MAX)
+            .take(count)
+            .cloned()
+            .collect();
+        for entry in &entries {
+            let entries_read = match self.groups[group].entries_read {
+                Some(read) if !self.has_tombstones_from(entry.id) => Some(read + 1),
+                _ => self.entries_read_at(entry.id),
+            };
+            let group = self
+                .groups
+                .get_mut(group)
+                .expect("group was looked up above");
+            group.entries_read = entries_read;
+            group.last_delivered = entry.id;
+            if !noack {
+                group.deliver(entry.id, consumer, now, 1);
+            }
+        }
+        if !entries.is_empty() {
+            let group = self
+                .groups
+                .get_mut(group)
+                .expect("group was looked up above");
+            group.consumer(consumer, now).active_time = Some(now);
+        }
+        entries
+    }
+
+    /// Hands `consumer` again up to `count` of its pending entries from
+    /// `start` on. Entries deleted from the stream since come back as
+    /// `None`.
+    pub fn deliver_pending(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        start: StreamId,
+        count: usize,
+        now: u64,
+    ) -> Vec<(StreamId, Option<Entry>)> {
+        let ids: Vec<StreamId> = match self.groups[group].consumers.get(consumer) {
+            Some(consumer) => consumer
+                .pending
+                .range(start..)
+                .take(count)
+                .copied()
+                .collect(),
+            None => vec![],
+        };
+        let entries: Vec<_> = ids
+            .into_iter()
+            .map(|id| (id, self.get(id).cloned()))
+            .collect();
+        let group = self
+            .groups
+            .get_mut(group)
+            .expect("group was looked up above");
+        for (id, entry) in &entries {
+            if let (Some(_), Some(pending)) = (entry, group.pending.get_mut(id)) {
+                pending.delivery_time = now;
+                pending.delivery_count += 1;
+            }
+        }
+        entries
+    }
+
+    /// Trims the oldest entries as `trim` asks, returning how many went.
+    pub fn trim(&mut self, trim: Trim) -> usize {
+        let mut removed = 0;
+        while let Some(mut node) = self.nodes.first_entry() {
+            let entries = node.get();
+            let whole = match trim.threshold {
+                Threshold::MaxLen(max) => self.len - entries.len() >= max,
+                Threshold::MinId(min) => entries.last().is_some_and(|entry| entry.id < min),
+            };
+            if whole {
+                if trim
+                    .limit
+                    .is_some_and(|limit| removed + entries.len() > limit)
+                {
+                    break;
+                }
+                removed += entries.len();
+                self.len -= entries.len();
+                node.remove();
+                continue;
+            }
+            if !trim.approx {
+                let count = match trim.threshold {
+                    Threshold::MaxLen(max) => self.len.saturating_sub(max),
+                    Threshold::MinId(min) => {
+                        entries.iter().take_while(|entry| entry.id < min).count()
+                    }
+                };
+                node.get_mut().drain(..count);
+                removed += count;
+                self.len -= count;
+            }
+            break;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{ConsumerGroup, Stream, StreamId, Threshold, Trim};
+
+    fn stream(len: u64, node_max_entries: usize) -> Stream {
+        let mut stream = Stream::new();
+        for ms in 1..=len {
+            stream.append(
+                StreamId::new(ms, 0),
+                vec![("f".into(), "v".into())],
+                node_max_entries,
+            );
+        }
+        stream
+    }
+
+    fn ids<'a>(entries: impl Iterator<Item = &'a super::Entry>) -> Vec<u64> {
+        entries.map(|entry| entry.id.ms).collect()
+    }
+
+    #[test]
+    fn parses_and_orders_ids() {
+        assert_eq!(StreamId::parse(b"5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(
+            StreamId::parse(b"5", u64::MAX),
+            Some(StreamId::new(5, u64::MAX))
+        );
+        assert_eq!(StreamId::parse(b"5-", 0), None);
+        assert_eq!(StreamId::parse(b"-1", 0), None);
+        assert_eq!(
+            StreamId::parse_part(b"18446744073709551615"),
+            Some(u64::MAX)
+        );
+        assert_eq!(StreamId::parse_part(b"1-2"), None);
+        assert_eq!(StreamId::parse_part(b"+1"), None);
+        assert_eq!(StreamId::new(1, u64::MAX).next(), Some(StreamId::new(2, 0)));
+        assert_eq!(StreamId::MIN.prev(), None);
+        assert_eq!(StreamId::new(7, 1).to_string(), "7-1");
+    }
+
+    #[test]
+    fn ranges_across_nodes() {
+        let mut stream = stream(10, 3);
+        assert_eq!(stream.node_count(), 4);
+        let (start, end) = (StreamId::new(3, 1), StreamId::new(8, 0));
+        assert_eq!(ids(stream.range(start, end)), [4, 5, 6, 7, 8]);
+        assert_eq!(ids(stream.rev_range(start, end)), [8, 7, 6, 5, 4]);
+
+        // Deleting a node's first entry keeps it reachable.
+        assert!(stream.remove(StreamId::new(4, 0)));
+        assert!(!stream.remove(StreamId::new(4, 0)));
+        assert_eq!(ids(stream.range(StreamId::new(4, 0), end)), [5, 6, 7, 8]);
+        assert_eq!(stream.max_deleted_id, StreamId::new(4, 0));
+        assert_eq!(stream.len(), 9);
+    }
+
+    #[test]
+    fn approximate_trims_drop_whole_nodes() {
+        let mut stream = stream(10, 3);
+        let trim = |threshold, approx| Trim {
+            threshold,
+            approx,
+            limit: None,
+        };
+        // Only the first node can go without leaving fewer than 6.
+        assert_eq!(stream.trim(trim(Threshold::MaxLen(6), true)), 3);
+        assert_eq!(stream.len(), 7);
+        assert_eq!(stream.trim(trim(Threshold::MaxLen(6), false)), 1);
+        assert_eq!(stream.iter().next().unwrap().id.ms, 5);
+        assert_eq!(
+            stream.trim(trim(Threshold::MinId(StreamId::new(9, 0)), false)),
+            4
+        );
+        assert_eq!(ids(stream.iter()), [9, 10]);
+        assert_eq!(stream.last_id, StreamId::new(10, 0));
+    }
+
+    #[test]
+    fn groups_track_pending_entries() {
+        let mut stream = stream(5, 2);
+        stream
+            .groups
+            .insert(Bytes::from("g"), ConsumerGroup::new(StreamId::MIN, None));
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+
+        assert_eq!(
+            ids(stream.deliver_new(b"g", &alice, 3, false, 10).iter()),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            ids(stream.deliver_new(b"g", &bob, 10, false, 20).iter()),
+            [4, 5]
+        );
+        let group = &stream.groups[b"g".as_ref()];
+        assert_eq!(group.entries_read, Some(5));
+        assert_eq!(stream.lag(group), Some(0));
+
+        // Redelivering bumps the count; deleted entries come back empty.
+        stream.remove(StreamId::new(2, 0));
+        let again = stream.deliver_pending(b"g", b"alice", StreamId::MIN, 10, 30);
+        assert_eq!(again.len(), 3);
+        assert!(again[1].1.is_none());
+        let group = stream.groups.get_mut(b"g".as_ref()).unwrap();
+        assert_eq!(group.pending[&StreamId::new(1, 0)].delivery_count, 2);
+        assert_eq!(group.pending[&StreamId::new(2, 0)].delivery_count, 1);
+
+        // Claiming moves ownership, acknowledging drops the entry.
+        group.deliver(StreamId::new(3, 0), &bob, 40, 3);
+        assert_eq!(group.consumers[b"alice".as_ref()].pending.len(), 2);
+        assert!(group.ack(StreamId::new(4, 0)));
+        assert!(!group.ack(StreamId::new(4, 0)));
+        assert_eq!(group.delete_consumer(b"bob"), Some(2));
+        assert_eq!(group.pending.len(), 2);
+    }
+}
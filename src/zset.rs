@@ -0,0 +1,706 @@
+use std::cmp::Ordering;
+
+use bytes::Bytes;
+
+use crate::{
+    dict::Dict,
+    random::{random_index, random_u64},
+};
+
+/// A score interval such as `(1 5` or `-inf +inf`.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub max: f64,
+    pub min_exclusive: bool,
+    pub max_exclusive: bool,
+}
+
+impl ScoreRange {
+    fn above_min(&self, score: f64) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    fn below_max(&self, score: f64) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min > self.max || (self.min == self.max && (self.min_exclusive || self.max_exclusive))
+    }
+}
+
+/// One end of a lexicographical interval: `-`, `+`, `[member` or `(member`.
+#[derive(Clone, Debug)]
+pub enum LexBound {
+    NegativeInfinity,
+    PositiveInfinity,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+#[derive(Clone, Debug)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    fn above_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::NegativeInfinity => true,
+            LexBound::PositiveInfinity => false,
+            LexBound::Inclusive(min) => member >= min.as_ref(),
+            LexBound::Exclusive(min) => member > min.as_ref(),
+        }
+    }
+
+    fn below_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::NegativeInfinity => false,
+            LexBound::PositiveInfinity => true,
+            LexBound::Inclusive(max) => member <= max.as_ref(),
+            LexBound::Exclusive(max) => member < max.as_ref(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let bound = |bound: &LexBound| match bound {
+            LexBound::Inclusive(member) | LexBound::Exclusive(member) => Some(member.clone()),
+            _ => None,
+        };
+        match (&self.min, &self.max) {
+            (LexBound::PositiveInfinity, _) | (_, LexBound::NegativeInfinity) => true,
+            (LexBound::NegativeInfinity, _) | (_, LexBound::PositiveInfinity) => false,
+            (min, max) => {
+                let (low, high) = (bound(min).unwrap(), bound(max).unwrap());
+                let both_inclusive =
+                    matches!(min, LexBound::Inclusive(_)) && matches!(max, LexBound::Inclusive(_));
+                low > high || (low == high && !both_inclusive)
+            }
+        }
+    }
+}
+
+const MAX_LEVEL: usize = 32;
+const NIL: usize = usize::MAX;
+const HEAD: usize = 0;
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: usize,
+    /// Number of level-0 steps this link skips, for rank queries.
+    span: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+/// A skiplist ordered by (score, member), modelled on Redis' `zskiplist`,
+/// with spans on every link so ranks can be computed in O(log n).
+///
+/// Nodes live in an arena and link to each other by index; slot 0 is the
+/// header and freed slots are recycled.
+#[derive(Clone, Debug)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: usize,
+    len: usize,
+    level: usize,
+}
+
+impl Node {
+    /// Whether this node's entry sorts before (score, member).
+    fn precedes(&self, score: f64, member: &[u8]) -> bool {
+        match self.score.partial_cmp(&score) {
+            Some(Ordering::Less) => true,
+            Some(Ordering::Equal) => self.member.as_ref() < member,
+            _ => false,
+        }
+    }
+}
+
+/// Random level with a 1/4 chance of going up each step, like
+/// `zslRandomLevel`.
+fn random_level() -> usize {
+    let mut level = 1;
+    let mut bits = random_u64();
+    while level < MAX_LEVEL && bits & 3 == 0 {
+        level += 1;
+        bits >>= 2;
+    }
+    level
+}
+
+impl SkipList {
+    fn new() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        Self {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: NIL,
+            len: 0,
+            level: 1,
+        }
+    }
+
+    fn forward(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].forward
+    }
+
+    /// For each level, the last node before (score, member), and its rank.
+    fn find_predecessors(
+        &self,
+        score: f64,
+        member: &[u8],
+    ) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.nodes[next].precedes(score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Inserts an entry that must not already be present.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let (mut update, mut rank) = self.find_predecessors(score, &member);
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: if update[0] == HEAD { NIL } else { update[0] },
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i];
+            self.nodes[x].levels[i] = Level {
+                forward: previous.forward,
+                span: previous.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: x,
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[previous].levels[i].span += 1;
+        }
+
+        match self.forward(x, 0) {
+            NIL => self.tail = x,
+            next => self.nodes[next].backward = x,
+        }
+        self.len += 1;
+    }
+
+    /// Removes the entry (score, member), returning whether it was there.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.find_predecessors(score, member);
+        let x = self.forward(update[0], 0);
+        if x == NIL || self.nodes[x].score != score || self.nodes[x].member != member {
+            return false;
+        }
+
+        for (i, &previous) in update.iter().enumerate().take(self.level) {
+            if self.forward(previous, i) == x {
+                let removed = self.nodes[x].levels[i];
+                let link = &mut self.nodes[previous].levels[i];
+                link.span += removed.span;
+                link.span -= 1;
+                link.forward = removed.forward;
+            } else {
+                self.nodes[previous].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[x].backward;
+        match self.forward(x, 0) {
+            NIL => self.tail = backward,
+            next => self.nodes[next].backward = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1) == NIL {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        true
+    }
+
+    /// 1-based rank of (score, member), if present.
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL {
+                    break;
+                }
+                let node = &self.nodes[next];
+                if !node.precedes(score, member) && (node.score != score || node.member != member) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member == member {
+                return Some(rank);
+            }
+        }
+        None
+    }
+
+    /// The node at 1-based `rank`.
+    fn by_rank(&self, rank: usize) -> usize {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || traversed + self.nodes[x].levels[i].span > rank {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == rank {
+                return x;
+            }
+        }
+        NIL
+    }
+
+    /// The first node for which `past_start` holds, if `in_range` also holds
+    /// for it. `past_start` must be monotonic along the list.
+    fn first_where(
+        &self,
+        past_start: impl Fn(&Node) -> bool,
+        in_range: impl Fn(&Node) -> bool,
+    ) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || past_start(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        let x = self.forward(x, 0);
+        if x != NIL && in_range(&self.nodes[x]) {
+            x
+        } else {
+            NIL
+        }
+    }
+
+    /// The last node for which `before_end` holds, if `in_range` also holds
+    /// for it. `before_end` must be monotonic along the list.
+    fn last_where(
+        &self,
+        before_end: impl Fn(&Node) -> bool,
+        in_range: impl Fn(&Node) -> bool,
+    ) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !before_end(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        if x != HEAD && in_range(&self.nodes[x]) {
+            x
+        } else {
+            NIL
+        }
+    }
+
+    fn first_in_score_range(&self, range: &ScoreRange) -> usize {
+        if range.is_empty() {
+            return NIL;
+        }
+        self.first_where(
+            |node| range.above_min(node.score),
+            |node| range.below_max(node.score),
+        )
+    }
+
+    fn last_in_score_range(&self, range: &ScoreRange) -> usize {
+        if range.is_empty() {
+            return NIL;
+        }
+        self.last_where(
+            |node| range.below_max(node.score),
+            |node| range.above_min(node.score),
+        )
+    }
+
+    fn first_in_lex_range(&self, range: &LexRange) -> usize {
+        if range.is_empty() {
+            return NIL;
+        }
+        self.first_where(
+            |node| range.above_min(&node.member),
+            |node| range.below_max(&node.member),
+        )
+    }
+
+    fn last_in_lex_range(&self, range: &LexRange) -> usize {
+        if range.is_empty() {
+            return NIL;
+        }
+        self.last_where(
+            |node| range.below_max(&node.member),
+            |node| range.above_min(&node.member),
+        )
+    }
+
+    /// Walks from `start` towards the tail (or the head if `reverse`).
+    fn walk(&self, start: usize, reverse: bool) -> impl Iterator<Item = &Node> + '_ {
+        let mut x = start;
+        std::iter::from_fn(move || {
+            if x == NIL {
+                return None;
+            }
+            let node = &self.nodes[x];
+            x = if reverse {
+                node.backward
+            } else {
+                node.levels[0].forward
+            };
+            Some(node)
+        })
+    }
+}
+
+/// Which entries of a sorted set a range query selects.
+#[derive(Clone, Debug)]
+pub enum Range {
+    /// Inclusive 0-based ranks, already clamped to the set.
+    Rank(usize, usize),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+/// The value of a sorted set key: a skiplist ordered by score for range
+/// queries, plus a dict from member to score for O(1) lookups, as in Redis.
+#[derive(Clone, Debug)]
+pub struct SortedSet {
+    dict: Dict<Bytes, f64>,
+    list: SkipList,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self {
+            dict: Dict::new(),
+            list: SkipList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.dict.get(member).copied()
+    }
+
+    /// Sets the score of `member`, adding it if needed. Returns the score it
+    /// had before.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        match self.dict.get_mut(&member) {
+            Some(current) if *current == score => Some(score),
+            Some(current) => {
+                let previous = std::mem::replace(current, score);
+                self.list.remove(previous, &member);
+                self.list.insert(score, member);
+                Some(previous)
+            }
+            None => {
+                self.dict.insert(member.clone(), score);
+                self.list.insert(score, member);
+                None
+            }
+        }
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.dict.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// 0-based rank of `member`, counting from the highest score if
+    /// `reverse`.
+    pub fn rank(&self, member: &[u8], reverse: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)? - 1;
+        Some(if reverse { self.len() - 1 - rank } else { rank })
+    }
+
+    /// Entries in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.list
+            .walk(self.list.forward(HEAD, 0), false)
+            .map(|node| (&node.member, node.score))
+    }
+
+    /// The node where a walk over `range` starts, going forwards or, if
+    /// `reverse`, backwards.
+    fn range_start(&self, range: &Range, reverse: bool) -> usize {
+        match (range, reverse) {
+            (Range::Rank(start, _), false) => self.list.by_rank(start + 1),
+            (Range::Rank(_, stop), true) => self.list.by_rank(stop + 1),
+            (Range::Score(range), false) => self.list.first_in_score_range(range),
+            (Range::Score(range), true) => self.list.last_in_score_range(range),
+            (Range::Lex(range), false) => self.list.first_in_lex_range(range),
+            (Range::Lex(range), true) => self.list.last_in_lex_range(range),
+        }
+    }
+
+    /// Entries selected by `range`, in ascending order or descending if
+    /// `reverse`, skipping `offset` and returning at most `limit`.
+    pub fn range(
+        &self,
+        range: &Range,
+        reverse: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let start = self.range_start(range, reverse);
+        let in_range = |node: &Node| match range {
+            Range::Rank(..) => true,
+            Range::Score(range) => range.above_min(node.score) && range.below_max(node.score),
+            Range::Lex(range) => range.above_min(&node.member) && range.below_max(&node.member),
+        };
+        let len = match range {
+            Range::Rank(start, stop) => stop + 1 - start,
+            _ => usize::MAX,
+        };
+        self.list
+            .walk(start, reverse)
+            .take(len)
+            .take_while(|node| in_range(node))
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    /// Number of entries selected by `range`, computed from ranks.
+    pub fn count(&self, range: &Range) -> usize {
+        let first = self.range_start(range, false);
+        if first == NIL {
+            return 0;
+        }
+        let last = self.range_start(range, true);
+        let rank = |x: usize| {
+            let node = &self.list.nodes[x];
+            self.list
+                .rank(node.score, &node.member)
+                .expect("node is in the list")
+        };
+        rank(last) + 1 - rank(first)
+    }
+
+    /// Removes and returns the entries selected by `range`.
+    pub fn remove_range(&mut self, range: &Range) -> usize {
+        let removed = self.range(range, false, 0, None);
+        for (member, _) in &removed {
+            self.remove(member);
+        }
+        removed.len()
+    }
+
+    /// Removes and returns up to `count` entries from the lowest scores, or
+    /// the highest if `max`.
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        let start = if max {
+            self.list.tail
+        } else {
+            self.list.forward(HEAD, 0)
+        };
+        let popped: Vec<(Bytes, f64)> = self
+            .list
+            .walk(start, max)
+            .take(count)
+            .map(|node| (node.member.clone(), node.score))
+            .collect();
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+
+    pub fn random_entry(&self) -> Option<(&Bytes, f64)> {
+        self.dict
+            .random_entry()
+            .map(|(member, &score)| (member, score))
+    }
+
+    /// Up to `count` distinct random entries.
+    pub fn random_distinct(&self, count: usize) -> Vec<(Bytes, f64)> {
+        let mut entries: Vec<(Bytes, f64)> = self
+            .iter()
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+        let count = count.min(entries.len());
+        for i in 0..count {
+            let j = i + random_index(entries.len() - i);
+            entries.swap(i, j);
+        }
+        entries.truncate(count);
+        entries
+    }
+
+    /// One `ZSCAN` step over the member dict.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&Bytes, f64)) -> u64 {
+        self.dict
+            .scan_many(cursor, count, |member, &score| visit(member, score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{LexBound, LexRange, Range, ScoreRange, SortedSet};
+
+    fn members(entries: Vec<(Bytes, f64)>) -> Vec<String> {
+        entries
+            .into_iter()
+            .map(|(member, _)| String::from_utf8(member.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ranks_follow_score_then_member() {
+        let mut zset = SortedSet::new();
+        for i in 0..200 {
+            zset.insert(format!("m{:03}", i).into(), (i % 10) as f64);
+        }
+        assert_eq!(zset.len(), 200);
+        // Score 0 holds m000, m010, ..., m190; then score 1 starts at m001.
+        assert_eq!(zset.rank(b"m190", false), Some(19));
+        assert_eq!(zset.rank(b"m001", false), Some(20));
+        assert_eq!(zset.rank(b"m199", true), Some(0));
+
+        // Moving an entry keeps spans consistent.
+        assert_eq!(zset.insert("m001".into(), -1.0), Some(1.0));
+        assert_eq!(zset.rank(b"m001", false), Some(0));
+        assert!(zset.remove(b"m000"));
+        assert!(!zset.remove(b"m000"));
+        assert_eq!(zset.rank(b"m010", false), Some(1));
+        for (rank, (member, _)) in zset.iter().enumerate() {
+            assert_eq!(zset.rank(member, false), Some(rank));
+        }
+    }
+
+    #[test]
+    fn score_and_lex_ranges() {
+        let mut zset = SortedSet::new();
+        for (i, member) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            zset.insert(Bytes::from(*member), i as f64);
+        }
+        let scores = Range::Score(ScoreRange {
+            min: 1.0,
+            max: 3.0,
+            min_exclusive: true,
+            max_exclusive: false,
+        });
+        assert_eq!(members(zset.range(&scores, false, 0, None)), ["c", "d"]);
+        assert_eq!(members(zset.range(&scores, true, 1, Some(5))), ["c"]);
+        assert_eq!(zset.count(&scores), 2);
+        assert_eq!(
+            members(zset.range(&Range::Rank(1, 2), true, 0, None)),
+            ["c", "b"]
+        );
+
+        let lex = Range::Lex(LexRange {
+            min: LexBound::Inclusive("b".into()),
+            max: LexBound::PositiveInfinity,
+        });
+        assert_eq!(zset.count(&lex), 4);
+        assert_eq!(zset.remove_range(&lex), 4);
+        assert_eq!(members(zset.pop(5, true)), ["a"]);
+        assert!(zset.is_empty());
+    }
+}